@@ -1,116 +1,551 @@
 use std::fs;
-use std::collections::HashSet;
-
-fn parse_token(token: &String) -> (String, i32) {
-  let dir = token.get(0..1).unwrap().to_string();
-  let step = token.get(1..).unwrap().parse::<i32>().unwrap();
-  return (dir, step);
-}
-fn parse_line(line: &String) -> Vec<(String, i32)> {
-  return line.split(",").map(|s| parse_token(&s.to_string())).collect();
-}
-
-fn parse(filename: impl AsRef<std::path::Path>) -> Vec<Vec<(String, i32)>> {
-  return fs::read_to_string(filename).expect("Something went wrong reading the file")
-   .lines().map(|s| parse_line(&s.to_string())).collect();
-}
-
-fn populate_grid(path: &Vec<(String, i32)>, grid : &mut HashSet<(i32,i32)>) {
-  let mut pos_x: i32 = 0;
-  let mut pos_y: i32 = 0;
-  for section in path {
-    let dir_char = &section.0[..];
-    let mut dir_x: i32 = 0;
-    let mut dir_y: i32 = 0;
-    match dir_char {
-      "U" => dir_y = 1,
-      "D" => dir_y = -1,
-      "L" => dir_x = -1,
-      "R" => dir_x = 1,
-      _ => panic!("unexpected direction")
-    }
-    let step = section.1;
-    for _ in 0..step {
-      pos_x += dir_x;
-      pos_y += dir_y;
-      grid.insert((pos_x,pos_y));
-    }
-  }
-}
-
-fn compute_manhattan(p: &(i32, i32)) -> i32{
-  return p.0.abs() + p.1.abs();
-}
-
-fn cmp_manhattan(p1: &(i32,i32), p2: &(i32,i32)) -> std::cmp::Ordering {
-  let d1 = compute_manhattan(p1);
-  let d2 = compute_manhattan(p2);
-  return d1.cmp(&d2);
-}
-
-fn get_path_intersection(path1: &Vec<(String, i32)>, path2: &Vec<(String, i32)>) -> Vec<(i32,i32)> {
-  let mut grid1 = HashSet::new();
-  let mut grid2 = HashSet::new();
-  populate_grid(path1, &mut grid1);
-  populate_grid(path2, &mut grid2);
-  return grid1.intersection(&grid2).map(|t| (t.0,t.1)).collect::<Vec<(i32,i32)>>();
-}
-
-fn get_inter_cost(path: &Vec<(String, i32)>, inter: &(i32,i32)) -> i32 {
-  let mut pos_x: i32 = 0;
-  let mut pos_y: i32 = 0;
-  let mut cost: i32 = 0;
-  for section in path {
-    let dir_char = &section.0[..];
-    let mut dir_x: i32 = 0;
-    let mut dir_y: i32 = 0;
-    match dir_char {
-      "U" => dir_y = 1,
-      "D" => dir_y = -1,
-      "L" => dir_x = -1,
-      "R" => dir_x = 1,
-      _ => panic!("unexpected direction")
-    }
-    let step = section.1;
-    for _ in 0..step {
-      pos_x += dir_x;
-      pos_y += dir_y;
-      cost += 1;
-      if pos_x == inter.0 && pos_y == inter.1 {
-        return cost;
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+  Up,
+  Down,
+  Left,
+  Right,
+  UpLeft,
+  UpRight,
+  DownLeft,
+  DownRight,
+}
+
+impl Direction {
+  fn delta(self) -> (i64, i64) {
+    match self {
+      Direction::Up => (0, 1),
+      Direction::Down => (0, -1),
+      Direction::Left => (-1, 0),
+      Direction::Right => (1, 0),
+      Direction::UpLeft => (-1, 1),
+      Direction::UpRight => (1, 1),
+      Direction::DownLeft => (-1, -1),
+      Direction::DownRight => (1, -1),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Step {
+  dir: Direction,
+  len: i32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ParseError {
+  line: usize,
+  column: usize,
+  message: String,
+}
+
+impl std::fmt::Display for ParseError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+  }
+}
+
+// Repeats are expanded while parsing; a line may not expand to more steps
+// than this.
+const MAX_STEPS : usize = 1_000_000;
+
+// Recursive descent over one line of wire description:
+//
+//   wire   := item (',' item)*
+//   item   := step | '(' wire ')' '*' count
+//   step   := dir count
+//   dir    := 'U' | 'D' | 'L' | 'R' | 'UL' | 'UR' | 'DL' | 'DR'
+//
+// Diagonal steps move one unit on both axes per step. A group followed by
+// `*n` is repeated n times, so `(R2,U1)*3` is `R2,U1,R2,U1,R2,U1`.
+// Blanks are allowed between any two tokens.
+struct LineParser {
+  chars: Vec<char>,
+  pos: usize,
+  line: usize,
+}
+
+impl LineParser {
+  fn new(text: &str, line: usize) -> LineParser {
+    LineParser { chars: text.chars().collect(), pos: 0, line }
+  }
+
+  fn error(&self, message: impl Into<String>) -> ParseError {
+    ParseError { line: self.line, column: self.pos + 1, message: message.into() }
+  }
+
+  fn skip_blanks(&mut self) {
+    while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+      self.pos += 1;
+    }
+  }
+
+  fn peek(&mut self) -> Option<char> {
+    self.skip_blanks();
+    self.chars.get(self.pos).cloned()
+  }
+
+  fn expect(&mut self, c: char) -> Result<(), ParseError> {
+    match self.peek() {
+      Some(found) if found == c => { self.pos += 1; Ok(()) }
+      Some(found) => Err(self.error(format!("expected '{}', found '{}'", c, found))),
+      None => Err(self.error(format!("expected '{}', found end of line", c))),
+    }
+  }
+
+  fn count(&mut self) -> Result<i32, ParseError> {
+    self.skip_blanks();
+    let start = self.pos;
+    while self.pos < self.chars.len() && self.chars[self.pos].is_ascii_digit() {
+      self.pos += 1;
+    }
+    if start == self.pos {
+      return Err(self.error("expected a number"));
+    }
+    let digits : String = self.chars[start..self.pos].iter().collect();
+    digits.parse::<i32>().map_err(|_| ParseError { line: self.line, column: start + 1, message: format!("number too large: {}", digits) })
+  }
+
+  fn direction(&mut self) -> Result<Direction, ParseError> {
+    let first = match self.peek() {
+      Some(c) => c,
+      None => return Err(self.error("expected a direction, found end of line")),
+    };
+    self.pos += 1;
+    let second = self.chars.get(self.pos).cloned();
+    let dir = match (first, second) {
+      ('U', Some('L')) => Direction::UpLeft,
+      ('U', Some('R')) => Direction::UpRight,
+      ('D', Some('L')) => Direction::DownLeft,
+      ('D', Some('R')) => Direction::DownRight,
+      ('U', _) => return Ok(Direction::Up),
+      ('D', _) => return Ok(Direction::Down),
+      ('L', _) => return Ok(Direction::Left),
+      ('R', _) => return Ok(Direction::Right),
+      (c, _) => {
+        self.pos -= 1;
+        return Err(self.error(format!("expected a direction, found '{}'", c)));
+      }
+    };
+    self.pos += 1;
+    Ok(dir)
+  }
+
+  fn item(&mut self, steps: &mut Vec<Step>) -> Result<(), ParseError> {
+    if self.peek() == Some('(') {
+      self.pos += 1;
+      let mut group = Vec::new();
+      self.wire(&mut group)?;
+      self.expect(')')?;
+      self.expect('*')?;
+      self.skip_blanks();
+      let column = self.pos + 1;
+      let count = self.count()?;
+      let total = (group.len() as u64) * (count.max(0) as u64) + steps.len() as u64;
+      if total > MAX_STEPS as u64 {
+        return Err(ParseError { line: self.line, column, message: format!("repeat expands to more than {} steps", MAX_STEPS) });
       }
+      for _ in 0..count {
+        steps.extend_from_slice(&group);
+      }
+      return Ok(());
+    }
+    let dir = self.direction()?;
+    let len = self.count()?;
+    steps.push(Step { dir, len });
+    Ok(())
+  }
+
+  fn wire(&mut self, steps: &mut Vec<Step>) -> Result<(), ParseError> {
+    self.item(steps)?;
+    while self.peek() == Some(',') {
+      self.pos += 1;
+      self.item(steps)?;
+    }
+    Ok(())
+  }
+
+  fn parse(mut self) -> Result<Vec<Step>, ParseError> {
+    let mut steps = Vec::new();
+    self.wire(&mut steps)?;
+    match self.peek() {
+      None => Ok(steps),
+      Some(c) => Err(self.error(format!("unexpected '{}'", c))),
+    }
+  }
+}
+
+fn parse_line(line: &str, number: usize) -> Result<Vec<Step>, ParseError> {
+  LineParser::new(line, number).parse()
+}
+
+// One wire per non blank line; lines and columns in errors count from 1.
+fn parse_str(text: &str) -> Result<Vec<Vec<Step>>, ParseError> {
+  text.lines().enumerate()
+    .filter(|(_, line)| !line.trim().is_empty())
+    .map(|(i, line)| parse_line(line, i + 1))
+    .collect()
+}
+
+fn parse(filename: impl AsRef<std::path::Path>) -> Vec<Vec<Step>> {
+  let text = fs::read_to_string(filename).expect("Something went wrong reading the file");
+  parse_str(&text).unwrap_or_else(|e| panic!("invalid wire description: {}", e))
+}
+
+// One straight piece of a wire. `delay` is the number of steps the wire has
+// taken when it reaches `start`; each of the `len` unit moves of `dir`
+// toward `end` adds one (a diagonal move counts as a single step).
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+  wire: usize,
+  start: (i64, i64),
+  end: (i64, i64),
+  dir: (i64, i64),
+  len: i64,
+  delay: i64,
+}
+
+impl Segment {
+  fn is_horizontal(&self) -> bool {
+    self.dir.1 == 0
+  }
+
+  fn is_vertical(&self) -> bool {
+    self.dir.0 == 0
+  }
+
+  fn at(&self, t: i64) -> (i64, i64) {
+    (self.start.0 + t * self.dir.0, self.start.1 + t * self.dir.1)
+  }
+
+  fn x_range(&self) -> (i64, i64) {
+    (self.start.0.min(self.end.0), self.start.0.max(self.end.0))
+  }
+
+  fn y_range(&self) -> (i64, i64) {
+    (self.start.1.min(self.end.1), self.start.1.max(self.end.1))
+  }
+
+  fn delay_at(&self, p: (i64, i64)) -> i64 {
+    self.delay + (p.0 - self.start.0).abs().max((p.1 - self.start.1).abs())
+  }
+}
+
+fn to_segments(wire: usize, path: &[Step]) -> Vec<Segment> {
+  let mut segments = Vec::new();
+  let mut pos = (0i64, 0i64);
+  let mut delay = 0i64;
+  for step in path {
+    let dir = step.dir.delta();
+    let len = step.len as i64;
+    let end = (pos.0 + len * dir.0, pos.1 + len * dir.1);
+    if len > 0 {
+      segments.push(Segment { wire, start: pos, end, dir, len, delay });
     }
+    pos = end;
+    delay += len;
   }
-  panic!("intersection not found")
+  segments
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Crossing {
+  pos: (i64, i64),
+  wires: (usize, usize),
+  delay: i64,
+}
+
+fn manhattan(p: (i64, i64)) -> i64 {
+  p.0.abs() + p.1.abs()
 }
 
-fn q2(filename: &str) -> i32{
+#[derive(Debug, Default)]
+struct WireReport {
+  crossings: Vec<Crossing>,
+  closest: Option<i64>,
+  fastest: Option<i64>,
+}
+
+impl WireReport {
+  fn add(&mut self, s1: &Segment, s2: &Segment, pos: (i64, i64)) {
+    if s1.wire == s2.wire || pos == (0, 0) {
+      return;
+    }
+    let (s1, s2) = if s1.wire < s2.wire { (s1, s2) } else { (s2, s1) };
+    let crossing = Crossing { pos, wires: (s1.wire, s2.wire), delay: s1.delay_at(pos) + s2.delay_at(pos) };
+    let dist = manhattan(pos);
+    self.closest = Some(self.closest.map_or(dist, |d| d.min(dist)));
+    self.fastest = Some(self.fastest.map_or(crossing.delay, |d| d.min(crossing.delay)));
+    self.crossings.push(crossing);
+  }
+
+  // Two collinear segments share the whole range [lo, hi] on their line.
+  // Every point there is a crossing, so only the points that can be optimal
+  // are kept: both ends (delay is linear along the overlap) and the points
+  // around each `pivot`, where the line meets an axis and the Manhattan
+  // distance changes slope. The origin itself never counts.
+  fn add_overlap(&mut self, s1: &Segment, s2: &Segment, lo: i64, hi: i64, pivots: &[i64], at: impl Fn(i64) -> (i64, i64)) {
+    let mut candidates = vec![lo, hi];
+    for p in pivots {
+      candidates.extend_from_slice(&[p - 1, *p, p + 1]);
+    }
+    let mut candidates : Vec<i64> = candidates.iter().map(|c| (*c).max(lo).min(hi)).collect();
+    candidates.sort();
+    candidates.dedup();
+    for c in candidates {
+      self.add(s1, s2, at(c));
+    }
+  }
+}
+
+// Overlaps between segments lying on the same line. Segments are sorted by
+// line then by start, so each one only needs comparing with the followers
+// that start before it ends.
+fn collinear_overlaps(report: &mut WireReport, mut segs: Vec<(i64, i64, i64, Segment)>, horizontal: bool) {
+  segs.sort_by_key(|s| (s.0, s.1, s.2));
+  for i in 0..segs.len() {
+    let (line, _, hi1, s1) = segs[i];
+    for &(line2, lo2, hi2, s2) in &segs[i+1..] {
+      if line2 != line || lo2 > hi1 {
+        break;
+      }
+      let (lo, hi) = (lo2, hi1.min(hi2));
+      if horizontal {
+        report.add_overlap(&s1, &s2, lo, hi, &[0], |x| (x, line));
+      } else {
+        report.add_overlap(&s1, &s2, lo, hi, &[0], |y| (line, y));
+      }
+    }
+  }
+}
+
+// Sweep a vertical line from left to right. Horizontal segments are active
+// while the sweep is inside their x range, keyed by y, so every vertical
+// segment only visits the horizontals it actually crosses.
+fn perpendicular_crossings(report: &mut WireReport, horizontals: &[Segment], verticals: &[Segment]) {
+  // (x, kind, index): kind 0 = open a horizontal, 1 = vertical query, 2 = close
+  let mut events : Vec<(i64, u8, usize)> = Vec::new();
+  for (i, h) in horizontals.iter().enumerate() {
+    let (x0, x1) = h.x_range();
+    events.push((x0, 0, i));
+    events.push((x1, 2, i));
+  }
+  for (i, v) in verticals.iter().enumerate() {
+    events.push((v.start.0, 1, i));
+  }
+  events.sort();
+  let mut active : BTreeSet<(i64, usize)> = BTreeSet::new();
+  for (x, kind, i) in events {
+    match kind {
+      0 => { active.insert((horizontals[i].start.1, i)); }
+      2 => { active.remove(&(horizontals[i].start.1, i)); }
+      _ => {
+        let v = &verticals[i];
+        let (y0, y1) = v.y_range();
+        for &(y, h) in active.range((y0, 0)..=(y1, usize::MAX)) {
+          report.add(&horizontals[h], v, (x, y));
+        }
+      }
+    }
+  }
+}
+
+// Crossings of two segments in any direction, for the diagonals the sweep
+// does not cover. Positions solve `s1.at(t) == s2.at(u)` on the lattice:
+// a crossing between two grid points is not a crossing of the wires.
+fn any_crossings(report: &mut WireReport, s1: &Segment, s2: &Segment) {
+  let cross = |a: (i64, i64), b: (i64, i64)| a.0 * b.1 - a.1 * b.0;
+  let w = (s2.start.0 - s1.start.0, s2.start.1 - s1.start.1);
+  let denom = cross(s1.dir, s2.dir);
+  if denom == 0 {
+    if cross(w, s1.dir) != 0 {
+      return;
+    }
+    // Parallel and on the same line: express s2 with s1's parameter.
+    let t_of = |p: (i64, i64)| if s1.dir.0 != 0 { (p.0 - s1.start.0) * s1.dir.0 } else { (p.1 - s1.start.1) * s1.dir.1 };
+    let (a, b) = (t_of(s2.start), t_of(s2.end));
+    let (lo, hi) = (a.min(b).max(0), a.max(b).min(s1.len));
+    if lo > hi {
+      return;
+    }
+    let mut pivots = Vec::new();
+    if s1.dir.0 != 0 {
+      pivots.push(-s1.start.0 * s1.dir.0);
+    }
+    if s1.dir.1 != 0 {
+      pivots.push(-s1.start.1 * s1.dir.1);
+    }
+    report.add_overlap(s1, s2, lo, hi, &pivots, |t| s1.at(t));
+    return;
+  }
+  let (tn, un) = (cross(w, s2.dir), cross(w, s1.dir));
+  if tn % denom != 0 || un % denom != 0 {
+    return;
+  }
+  let (t, u) = (tn / denom, un / denom);
+  if t >= 0 && t <= s1.len && u >= 0 && u <= s2.len {
+    report.add(s1, s2, s1.at(t));
+  }
+}
+
+// Every crossing between two different wires, with the best Manhattan
+// distance and the best combined delay found along the way. Horizontal and
+// vertical segments go through the sweep; the few diagonal ones are checked
+// against everything.
+fn find_crossings(paths: &[Vec<Step>]) -> WireReport {
+  let mut horizontals = Vec::new();
+  let mut verticals = Vec::new();
+  let mut diagonals = Vec::new();
+  for (wire, path) in paths.iter().enumerate() {
+    for s in to_segments(wire, path) {
+      if s.is_horizontal() {
+        horizontals.push(s);
+      } else if s.is_vertical() {
+        verticals.push(s);
+      } else {
+        diagonals.push(s);
+      }
+    }
+  }
+  let mut report = WireReport::default();
+  perpendicular_crossings(&mut report, &horizontals, &verticals);
+  collinear_overlaps(&mut report, horizontals.iter().map(|s| (s.start.1, s.x_range().0, s.x_range().1, *s)).collect(), true);
+  collinear_overlaps(&mut report, verticals.iter().map(|s| (s.start.0, s.y_range().0, s.y_range().1, *s)).collect(), false);
+  for (i, d) in diagonals.iter().enumerate() {
+    for s in horizontals.iter().chain(verticals.iter()).chain(diagonals[i+1..].iter()) {
+      any_crossings(&mut report, d, s);
+    }
+  }
+  report
+}
+
+const WIRE_COLOURS : [&str; 6] = ["#1f77b4", "#d62728", "#2ca02c", "#9467bd", "#ff7f0e", "#17becf"];
+const SVG_SIZE : f64 = 800.0;
+const SVG_MARGIN : f64 = 20.0;
+
+// Draw the wires as an SVG picture: one polyline per wire, a small ring on
+// every crossing, and bigger rings on the closest (red) and fastest (blue)
+// ones. The drawing is scaled so its largest side is `SVG_SIZE` pixels, and
+// everything is written in a fixed order with fixed precision so the same
+// input always gives the same bytes.
+fn to_svg(paths: &[Vec<Step>]) -> String {
+  let wires : Vec<Vec<Segment>> = paths.iter().enumerate().map(|(w, p)| to_segments(w, p)).collect();
+  let mut report = find_crossings(paths);
+  report.crossings.sort_by_key(|c| (c.pos, c.wires));
+  let (mut min_x, mut max_x, mut min_y, mut max_y) = (0i64, 0i64, 0i64, 0i64);
+  for s in wires.iter().flatten() {
+    min_x = min_x.min(s.end.0);
+    max_x = max_x.max(s.end.0);
+    min_y = min_y.min(s.end.1);
+    max_y = max_y.max(s.end.1);
+  }
+  let scale = (SVG_SIZE - 2.0 * SVG_MARGIN) / ((max_x - min_x).max(max_y - min_y).max(1) as f64);
+  // SVG's y axis points down, wires go up.
+  let to_px = |p: (i64, i64)| ((p.0 - min_x) as f64 * scale + SVG_MARGIN, (max_y - p.1) as f64 * scale + SVG_MARGIN);
+  let width = (max_x - min_x) as f64 * scale + 2.0 * SVG_MARGIN;
+  let height = (max_y - min_y) as f64 * scale + 2.0 * SVG_MARGIN;
+
+  let mut svg = String::new();
+  svg += &format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.0}\" height=\"{:.0}\" viewBox=\"0 0 {:.0} {:.0}\">\n", width, height, width, height);
+  svg += "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n";
+  for (w, segments) in wires.iter().enumerate() {
+    let mut points = vec![to_px((0, 0))];
+    points.extend(segments.iter().map(|s| to_px(s.end)));
+    let points : Vec<String> = points.iter().map(|p| format!("{:.1},{:.1}", p.0, p.1)).collect();
+    svg += &format!("<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>\n", points.join(" "), WIRE_COLOURS[w % WIRE_COLOURS.len()]);
+  }
+  let circle = |p: (i64, i64), r: f64, colour: &str| {
+    let (x, y) = to_px(p);
+    format!("<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.0}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>\n", x, y, r, colour)
+  };
+  let (ox, oy) = to_px((0, 0));
+  svg += &format!("<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"4\" fill=\"black\"/>\n", ox, oy);
+  let mut last = None;
+  for c in &report.crossings {
+    if last != Some(c.pos) {
+      svg += &circle(c.pos, 4.0, "black");
+      last = Some(c.pos);
+    }
+  }
+  if let Some(c) = report.crossings.iter().min_by_key(|c| (manhattan(c.pos), c.pos)) {
+    svg += &circle(c.pos, 8.0, "red");
+  }
+  if let Some(c) = report.crossings.iter().min_by_key(|c| (c.delay, c.pos)) {
+    svg += &circle(c.pos, 12.0, "blue");
+  }
+  svg += "</svg>\n";
+  svg
+}
+
+fn q2(filename: &str) -> i64 {
   let paths = parse(filename);
-  let inter = get_path_intersection(&paths[0], &paths[1]);
-  let cost_inter1 = (&inter).into_iter().map(|itr| get_inter_cost(&paths[0], &itr));
-  let cost_inter2 = (&inter).into_iter().map(|itr| get_inter_cost(&paths[1], &itr));
-  let mut cost_inter : Vec<i32> = cost_inter1.zip(cost_inter2).map(|c| c.0+c.1).collect();
-  cost_inter.sort();
-  return cost_inter[0];
+  find_crossings(&paths).fastest.expect("wires never cross")
 }
 
-fn q1(filename: &str) -> i32{
+fn q1(filename: &str) -> i64 {
   let paths = parse(filename);
-  let mut inter = get_path_intersection(&paths[0], &paths[1]);
-  inter.sort_by(cmp_manhattan);
-  return compute_manhattan(&inter[0]);
+  find_crossings(&paths).closest.expect("wires never cross")
 }
 
 fn main() {
   println!("Question1: {}", q1("data.txt"));
   println!("Question1: {}", q2("data.txt"));
+  if let Some(output) = std::env::args().nth(1) {
+    fs::write(output, to_svg(&parse("data.txt"))).expect("Something went wrong writing the svg");
+  }
+}
+
+#[test]
+fn test_repeat_limit() {
+  let err = parse_line("((R1)*100000)*100000", 3).unwrap_err();
+  assert_eq!(err.to_string(), "line 3, column 15: repeat expands to more than 1000000 steps");
+  let err = parse_line("U1,(R1,L1)* 500000", 1).unwrap_err();
+  assert_eq!(err.column, 13);
+  assert_eq!(parse_line("U1,(R1,L1)*499999", 1).unwrap().len(), MAX_STEPS - 1);
 }
 
 #[test]
 fn test_parse_token() {
-  let ret = parse_token(&("R150".to_string()));
-  assert_eq!(ret.0, "R");
-  assert_eq!(ret.1, 150);
+  let ret = parse_line("R150", 1).unwrap();
+  assert_eq!(ret[0].dir, Direction::Right);
+  assert_eq!(ret[0].len, 150);
+  assert_eq!(parse_line("DL7", 1).unwrap(), vec![Step { dir: Direction::DownLeft, len: 7 }]);
+}
+
+#[test]
+fn test_parse_errors() {
+  let err = parse_str("R8,U5\nU7,X6").unwrap_err();
+  assert_eq!((err.line, err.column), (2, 4));
+  let err = parse_str("R8,U").unwrap_err();
+  assert_eq!((err.line, err.column), (1, 5));
+  assert_eq!(err.to_string(), "line 1, column 5: expected a number");
+  let err = parse_str("(R1,U1*2").unwrap_err();
+  assert_eq!((err.line, err.column), (1, 7));
+  assert!(parse_line("R99999999999", 1).is_err());
+}
+
+#[test]
+fn test_parse_extended() {
+  let paths = parse_str(" R8 , U5,L5,D3 \r\n\nU7,R6,D4,L4\n\n").unwrap();
+  assert_eq!(paths, parse("test1.txt"));
+  let repeated = parse_str("(R2, U1) * 2, (L1, (D1)*2)*1").unwrap();
+  assert_eq!(repeated, parse_str("R2,U1,R2,U1,L1,D1,D1").unwrap());
+}
+
+#[test]
+fn test_diagonal_crossings() {
+  // Lattice crossing of two diagonals, and two diagonals passing between points.
+  let paths = parse_str("UR4\nR4,UL4").unwrap();
+  let report = find_crossings(&paths);
+  assert_eq!(report.closest, Some(4));
+  assert_eq!(report.fastest, Some(2 + 4 + 2));
+  assert_eq!(find_crossings(&parse_str("UR1\nR1,UL1").unwrap()).closest, None);
+  // Diagonal overlap through the origin.
+  let overlap = find_crossings(&parse_str("UR3\nDL2,UR5").unwrap());
+  assert_eq!(overlap.closest, Some(2));
+  assert_eq!(overlap.fastest, Some(1 + 5));
 }
 
 #[test]
@@ -125,4 +560,42 @@ fn test_examples2() {
   assert_eq!(q2("test1.txt"), 30);
   assert_eq!(q2("test2.txt"), 610);
   assert_eq!(q2("test3.txt"), 410);
-}
\ No newline at end of file
+}
+#[test]
+fn test_collinear_overlap() {
+  let paths = vec![parse_line("R10", 1).unwrap(), parse_line("U1,R3,D1,R4", 1).unwrap()];
+  let report = find_crossings(&paths);
+  assert_eq!(report.closest, Some(3));
+  assert_eq!(report.fastest, Some(3 + 5));
+  let from_origin = find_crossings(&[parse_line("R5", 1).unwrap(), parse_line("R3", 1).unwrap()]);
+  assert_eq!(from_origin.closest, Some(1));
+  assert_eq!(from_origin.fastest, Some(2));
+}
+
+#[test]
+fn test_many_wires() {
+  let paths = vec![
+    parse_line("R8,U5,L5,D3", 1).unwrap(),
+    parse_line("U7,R6,D4,L4", 1).unwrap(),
+    parse_line("D1,R7,U3,R10", 1).unwrap(),
+  ];
+  let report = find_crossings(&paths);
+  assert_eq!(report.closest, Some(6));
+  assert_eq!(report.fastest, Some(16));
+  assert!(report.crossings.iter().any(|c| c.wires == (0, 2) && c.pos == (8, 2)));
+  assert!(report.crossings.iter().all(|c| c.wires.0 < c.wires.1));
+}
+
+#[test]
+fn test_svg() {
+  let expected = fs::read_to_string("test1.svg").expect("Something went wrong reading the file");
+  assert_eq!(to_svg(&parse("test1.txt")), expected);
+  let expected = fs::read_to_string("test2.svg").expect("Something went wrong reading the file");
+  let svg = to_svg(&parse("test2.txt"));
+  assert_eq!(svg, expected);
+  assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"800\" height=\"509\" viewBox=\"0 0 800 509\">"));
+  assert_eq!(svg.matches("<polyline ").count(), 2);
+  assert_eq!(svg.matches("stroke=\"black\"").count(), 4);
+  assert_eq!(svg.matches("r=\"8\" fill=\"none\" stroke=\"red\"").count(), 1);
+  assert_eq!(svg.matches("r=\"12\" fill=\"none\" stroke=\"blue\"").count(), 1);
+}
@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+use std::collections::HashMap;
 
 fn check1(n: i32) -> bool {
   let mut acc = n;
@@ -51,29 +53,196 @@ fn check2(n: i32) -> bool {
 }
 
 
-fn q1(s: i32, e: i32) -> i32 {
-  let mut count : i32 = 0;
-  for i in s..e {
-    if check1(i) {
-     count += 1;
+// Constraints a password must satisfy. A "run" is a maximal group of equal
+// adjacent digits, so 111122 has a run of four 1s and a run of two 2s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+  NonDecreasing,
+  AtLeastOnePair,
+  ExactPair,
+  // at least one run has a length in min..=max
+  SomeRun { min: u8, max: u8 },
+  // every run has a length in min..=max
+  EveryRun { min: u8, max: u8 },
+}
+
+// Where the digit automaton stands after reading a prefix: the last digit,
+// the length of the run it belongs to, and which `SomeRun` rules are
+// already satisfied by a closed run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct DigitState {
+  started: bool,
+  prev: u8,
+  run: u8,
+  mask: u32,
+}
+
+struct Walk {
+  lo: Vec<u8>,
+  hi: Vec<u8>,
+  memo: HashMap<(usize, DigitState), u64>,
+  out: Option<Vec<u64>>,
+}
+
+const MAX_DIGITS : usize = 18;
+
+// Counts the numbers of a range that satisfy every rule with a digit DP:
+// numbers are read digit by digit, and every prefix that is no longer
+// bound by the range limits is summarized by its `DigitState`, so the
+// count for the remaining digits is computed once per state.
+struct PasswordRules {
+  non_decreasing: bool,
+  some: Vec<(u8, u8)>,
+  every: Vec<(u8, u8)>,
+}
+
+impl PasswordRules {
+  fn new(rules: &[Rule]) -> PasswordRules {
+    let mut ret = PasswordRules { non_decreasing: false, some: Vec::new(), every: Vec::new() };
+    for rule in rules {
+      match *rule {
+        Rule::NonDecreasing => ret.non_decreasing = true,
+        Rule::AtLeastOnePair => ret.some.push((2, u8::MAX)),
+        Rule::ExactPair => ret.some.push((2, 2)),
+        Rule::SomeRun { min, max } => ret.some.push((min, max)),
+        Rule::EveryRun { min, max } => ret.every.push((min, max)),
+      }
     }
+    assert!(ret.some.len() <= 32, "too many run rules");
+    ret
+  }
+
+  fn close_run(&self, st: &DigitState) -> Option<u32> {
+    if self.every.iter().any(|&(min, max)| st.run < min || st.run > max) {
+      return None;
+    }
+    let mut mask = st.mask;
+    for (i, &(min, max)) in self.some.iter().enumerate() {
+      if st.run >= min && st.run <= max {
+        mask |= 1 << i;
+      }
+    }
+    Some(mask)
+  }
+
+  fn step(&self, st: DigitState, d: u8) -> Option<DigitState> {
+    if !st.started {
+      if d == 0 {
+        return Some(st);
+      }
+      return Some(DigitState { started: true, prev: d, run: 1, mask: 0 });
+    }
+    if d == st.prev {
+      let run = st.run + 1;
+      if self.every.iter().any(|&(_, max)| run > max) {
+        return None;
+      }
+      return Some(DigitState { run, ..st });
+    }
+    if self.non_decreasing && d < st.prev {
+      return None;
+    }
+    let mask = self.close_run(&st)?;
+    Some(DigitState { started: true, prev: d, run: 1, mask })
+  }
+
+  fn accept(&self, st: DigitState) -> bool {
+    // all zeros: the number 0, a single run of one digit
+    let st = if st.started { st } else { DigitState { started: true, prev: 0, run: 1, mask: 0 } };
+    match self.close_run(&st) {
+      Some(mask) => mask.count_ones() as usize == self.some.len(),
+      None => false,
+    }
+  }
+
+  fn walk(&self, ctx: &mut Walk, pos: usize, st: DigitState, tight_lo: bool, tight_hi: bool, value: u64) -> u64 {
+    if pos == ctx.hi.len() {
+      if !self.accept(st) {
+        return 0;
+      }
+      if let Some(out) = &mut ctx.out {
+        out.push(value);
+      }
+      return 1;
+    }
+    let free = !tight_lo && !tight_hi;
+    if free {
+      if ctx.out.is_none() {
+        if let Some(count) = ctx.memo.get(&(pos, st)) {
+          return *count;
+        }
+      } else {
+        // When listing, skip the subtrees that hold no match at all.
+        let out = ctx.out.take();
+        let count = self.walk(ctx, pos, st, false, false, value);
+        ctx.out = out;
+        if count == 0 {
+          return 0;
+        }
+      }
+    }
+    let dlo = if tight_lo { ctx.lo[pos] } else { 0 };
+    let dhi = if tight_hi { ctx.hi[pos] } else { 9 };
+    let mut total = 0;
+    for d in dlo..=dhi {
+      if let Some(next) = self.step(st, d) {
+        total += self.walk(ctx, pos + 1, next, tight_lo && d == dlo, tight_hi && d == dhi, value * 10 + d as u64);
+      }
+    }
+    if free && ctx.out.is_none() {
+      ctx.memo.insert((pos, st), total);
+    }
+    total
   }
-  return count;
-}
 
-fn q2(s: i32, e: i32) -> i32 {
-  let mut count : i32 = 0;
-  for i in s..e {
-    if check2(i) {
-     count += 1;
+  fn run(&self, lo: u64, hi: u64, list: bool) -> (u64, Vec<u64>) {
+    if lo > hi {
+      return (0, Vec::new());
     }
+    let hi_digits : Vec<u8> = hi.to_string().bytes().map(|b| b - b'0').collect();
+    assert!(hi_digits.len() <= MAX_DIGITS, "numbers are limited to {} digits", MAX_DIGITS);
+    // both bounds are written on the same width, `lo` padded with zeros
+    let lo_digits : Vec<u8> = format!("{:0width$}", lo, width = hi_digits.len()).bytes().map(|b| b - b'0').collect();
+    let mut ctx = Walk { lo: lo_digits, hi: hi_digits, memo: HashMap::new(), out: if list { Some(Vec::new()) } else { None } };
+    let start = DigitState { started: false, prev: 0, run: 0, mask: 0 };
+    let count = self.walk(&mut ctx, 0, start, true, true, 0);
+    (count, ctx.out.unwrap_or_default())
+  }
+
+  // Number of matching passwords in lo..=hi.
+  fn count(&self, lo: u64, hi: u64) -> u64 {
+    self.run(lo, hi, false).0
   }
-  return count;
+
+  // Matching passwords in lo..=hi, in increasing order.
+  fn list(&self, lo: u64, hi: u64) -> Vec<u64> {
+    self.run(lo, hi, true).1
+  }
+
+  fn matches(&self, n: u64) -> bool {
+    self.count(n, n) == 1
+  }
+}
+
+fn q1(lo: u64, hi: u64) -> u64 {
+  PasswordRules::new(&[Rule::NonDecreasing, Rule::AtLeastOnePair]).count(lo, hi)
+}
+
+fn q2(lo: u64, hi: u64) -> u64 {
+  PasswordRules::new(&[Rule::NonDecreasing, Rule::ExactPair]).count(lo, hi)
+}
+
+// The puzzle input is a range like `387638-919123`, given as first argument.
+fn parse_range(arg: &str) -> (u64, u64) {
+  let bounds : Vec<u64> = arg.trim().split('-').map(|s| s.parse::<u64>().expect("range should look like 387638-919123")).collect();
+  assert_eq!(bounds.len(), 2, "range should look like 387638-919123");
+  (bounds[0], bounds[1])
 }
 
 fn main() {
-    println!("Question1: {}", q1(387638,919123));
-    println!("Question2: {}", q2(387638,919123));
+    let (lo, hi) = parse_range(&std::env::args().nth(1).unwrap_or_else(|| "387638-919123".to_string()));
+    println!("Question1: {}", q1(lo, hi));
+    println!("Question2: {}", q2(lo, hi));
 }
 
 #[test]
@@ -89,3 +258,31 @@ fn test2() {
   assert_eq!(check2(123444), false);
   assert_eq!(check2(111122), true);
 }
+
+#[test]
+fn test_rules_match_brute_force() {
+  let rules1 = PasswordRules::new(&[Rule::NonDecreasing, Rule::AtLeastOnePair]);
+  let rules2 = PasswordRules::new(&[Rule::NonDecreasing, Rule::ExactPair]);
+  let brute1 = (100000..140000).filter(|n| check1(*n)).count() as u64;
+  let brute2 = (100000..140000).filter(|n| check2(*n)).count() as u64;
+  assert_eq!(rules1.count(100000, 139999), brute1);
+  assert_eq!(rules2.count(100000, 139999), brute2);
+  let listed : Vec<u64> = (100000..140000).filter(|n| check2(*n)).map(|n| n as u64).collect();
+  assert_eq!(rules2.list(100000, 139999), listed);
+  assert_eq!(q1(387638, 919123), (387638..=919123).filter(|n| check1(*n)).count() as u64);
+  assert_eq!(q2(387638, 919123), (387638..=919123).filter(|n| check2(*n)).count() as u64);
+}
+
+#[test]
+fn test_rules_custom() {
+  let triple = PasswordRules::new(&[Rule::SomeRun { min: 3, max: 3 }]);
+  assert!(triple.matches(1112));
+  assert!(!triple.matches(11112));
+  let short = PasswordRules::new(&[Rule::EveryRun { min: 1, max: 1 }]);
+  assert_eq!(short.count(0, 99), 10 + 81);
+  assert_eq!(short.count(10, 10), 1);
+  let rules = PasswordRules::new(&[Rule::NonDecreasing]);
+  assert_eq!(rules.count(0, 999_999_999_999_999_999), 4_686_825);
+  assert_eq!(rules.list(995, 1120), vec![999, 1111, 1112, 1113, 1114, 1115, 1116, 1117, 1118, 1119]);
+  assert_eq!(parse_range("387638-919123\n"), (387638, 919123));
+}
@@ -1,85 +1,630 @@
+#![allow(dead_code)]
 use std::collections::HashMap;
-use std::collections::HashSet;
 use std::fs;
 
+type NodeId = usize;
+
+// Orbit map stored as an indexed tree. Every object gets a `NodeId` in the
+// order it first appears in the input; all per-node data lives in vectors
+// indexed by that id.
 #[derive(Debug)]
-struct Node {
-  name: String,
-  parent: String,
-  childs: HashSet<String>,
+struct OrbitTree {
+  names: Vec<String>,
+  ids: HashMap<String, NodeId>,
+  parent: Vec<Option<NodeId>>,
+  children: Vec<Vec<NodeId>>,
+  root: NodeId,
+  depth: Vec<usize>,
+  // up[k][v] is the 2^k-th ancestor of v, the root being its own ancestor
+  up: Vec<Vec<NodeId>>,
+  subtree: Vec<usize>,
 }
 
-fn parse_line(line: &str) -> (&str, &str) {
-  let arr : Vec<&str> = line.split(")").collect();
-  return (arr[0], arr[1]);
+impl OrbitTree {
+  fn new(edges: &[(&str, &str)], root: &str) -> OrbitTree {
+    let mut tree = OrbitTree {
+      names: Vec::new(),
+      ids: HashMap::new(),
+      parent: Vec::new(),
+      children: Vec::new(),
+      root: 0,
+      depth: Vec::new(),
+      up: Vec::new(),
+      subtree: Vec::new(),
+    };
+    for (center, satellite) in edges {
+      let c = tree.add_node(center);
+      let s = tree.add_node(satellite);
+      tree.parent[s] = Some(c);
+      tree.children[c].push(s);
+    }
+    tree.root = tree.add_node(root);
+    tree.index();
+    tree
+  }
+
+  fn add_node(&mut self, name: &str) -> NodeId {
+    if let Some(id) = self.ids.get(name) {
+      return *id;
+    }
+    let id = self.names.len();
+    self.names.push(name.to_string());
+    self.ids.insert(name.to_string(), id);
+    self.parent.push(None);
+    self.children.push(Vec::new());
+    id
+  }
+
+  // Depths, subtree sizes and the binary lifting table, from a breadth
+  // first walk down from the root.
+  fn index(&mut self) {
+    let n = self.names.len();
+    let mut order = vec![self.root];
+    self.depth = vec![0; n];
+    let mut i = 0;
+    while i < order.len() {
+      let node = order[i];
+      for &child in &self.children[node] {
+        self.depth[child] = self.depth[node] + 1;
+        order.push(child);
+      }
+      i += 1;
+    }
+    if order.len() != n {
+      panic!("{} objects are not connected to {}", n - order.len(), self.names[self.root]);
+    }
+    self.subtree = vec![1; n];
+    for &node in order.iter().rev() {
+      if let Some(p) = self.parent[node] {
+        self.subtree[p] += self.subtree[node];
+      }
+    }
+    let levels = (usize::BITS - n.leading_zeros()).max(1) as usize;
+    self.up = vec![(0..n).map(|v| self.parent[v].unwrap_or(v)).collect()];
+    for k in 1..levels {
+      let prev = &self.up[k-1];
+      let next = (0..n).map(|v| prev[prev[v]]).collect();
+      self.up.push(next);
+    }
+  }
+
+  fn id(&self, name: &str) -> Option<NodeId> {
+    self.ids.get(name).cloned()
+  }
+
+  fn name(&self, id: NodeId) -> &str {
+    &self.names[id]
+  }
+
+  fn depth(&self, id: NodeId) -> usize {
+    self.depth[id]
+  }
+
+  fn subtree_size(&self, id: NodeId) -> usize {
+    self.subtree[id]
+  }
+
+  fn ancestor(&self, mut id: NodeId, mut steps: usize) -> NodeId {
+    let mut k = 0;
+    while steps > 0 && k < self.up.len() {
+      if steps & 1 == 1 {
+        id = self.up[k][id];
+      }
+      steps >>= 1;
+      k += 1;
+    }
+    id
+  }
+
+  fn lca(&self, a: NodeId, b: NodeId) -> NodeId {
+    let (mut a, mut b) = if self.depth[a] >= self.depth[b] { (a, b) } else { (b, a) };
+    a = self.ancestor(a, self.depth[a] - self.depth[b]);
+    if a == b {
+      return a;
+    }
+    for k in (0..self.up.len()).rev() {
+      if self.up[k][a] != self.up[k][b] {
+        a = self.up[k][a];
+        b = self.up[k][b];
+      }
+    }
+    self.up[0][a]
+  }
+
+  // Number of edges on the path between two objects.
+  fn distance(&self, a: NodeId, b: NodeId) -> usize {
+    self.depth[a] + self.depth[b] - 2 * self.depth[self.lca(a, b)]
+  }
+
+  // Objects on the path from `a` to `b`, both included, in walking order.
+  fn path(&self, a: NodeId, b: NodeId) -> Vec<NodeId> {
+    let top = self.lca(a, b);
+    let mut up = vec![a];
+    while *up.last().unwrap() != top {
+      up.push(self.up[0][*up.last().unwrap()]);
+    }
+    let mut down = vec![b];
+    while *down.last().unwrap() != top {
+      down.push(self.up[0][*down.last().unwrap()]);
+    }
+    down.pop();
+    up.extend(down.iter().rev());
+    up
+  }
+
+  // Direct plus indirect orbits: every object orbits all its ancestors.
+  fn total_orbits(&self) -> usize {
+    self.depth.iter().sum()
+  }
+}
+
+#[derive(Debug, PartialEq)]
+enum OrbitIssue {
+  Malformed { line: usize, text: String },
+  MultipleParents { object: String, lines: Vec<usize> },
+  Cycle { objects: Vec<String>, lines: Vec<usize> },
+  Unreachable { object: String, size: usize, lines: Vec<usize> },
+  MissingRoot { root: String },
+}
+
+fn join_lines(lines: &[usize]) -> String {
+  lines.iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+impl std::fmt::Display for OrbitIssue {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      OrbitIssue::Malformed { line, text } => write!(f, "line {}: expected A)B, found {:?}", line, text),
+      OrbitIssue::MultipleParents { object, lines } => write!(f, "{} orbits several objects (lines {})", object, join_lines(lines)),
+      OrbitIssue::Cycle { objects, lines } => write!(f, "orbit cycle {} (lines {})", objects.join(")"), join_lines(lines)),
+      OrbitIssue::Unreachable { object, size, lines } => write!(f, "{} and {} other objects are not connected to the root (lines {})", object, size - 1, join_lines(lines)),
+      OrbitIssue::MissingRoot { root } => write!(f, "root {} does not appear in the map", root),
+    }
+  }
+}
+
+// One `center)satellite` edge with the input line it comes from (from 1).
+type Edge<'a> = (&'a str, &'a str, usize);
+
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+  let arr : Vec<&str> = line.trim().split(')').collect();
+  if arr.len() != 2 || arr[0].is_empty() || arr[1].is_empty() {
+    return None;
+  }
+  Some((arr[0], arr[1]))
 }
 
-fn create_new_node(name: &str) -> Node {
-  return Node {
-    name: name.to_string(),
-    parent: String::new(),
-    childs: HashSet::new()
+fn parse_edges(data: &str) -> (Vec<Edge<'_>>, Vec<OrbitIssue>) {
+  let mut edges = Vec::new();
+  let mut issues = Vec::new();
+  for (i, line) in data.lines().enumerate() {
+    if line.trim().is_empty() {
+      continue;
+    }
+    match parse_line(line) {
+      Some((center, satellite)) => edges.push((center, satellite, i + 1)),
+      None => issues.push(OrbitIssue::Malformed { line: i + 1, text: line.to_string() }),
+    }
   }
+  (edges, issues)
 }
 
-fn populate_tree(tree : &mut HashMap<String, Node>, edge : &(&str, &str)) {
-  tree.entry(edge.0.to_string()).or_insert(create_new_node(edge.0)).childs.insert(edge.1.to_string());
-  tree.entry(edge.1.to_string()).or_insert(create_new_node(edge.1)).parent = edge.0.to_string();
+// Everything that would keep the edges from forming a single tree hanging
+// from `root`: objects with several centers, orbit cycles, objects that
+// cannot be reached from the root, or a root that is not in the map.
+fn validate(edges: &[Edge], root: &str) -> Vec<OrbitIssue> {
+  let mut ids : HashMap<&str, usize> = HashMap::new();
+  let mut names : Vec<&str> = Vec::new();
+  for (center, satellite, _) in edges {
+    for name in [center, satellite].iter() {
+      if !ids.contains_key(*name) {
+        ids.insert(name, names.len());
+        names.push(name);
+      }
+    }
+  }
+  let n = names.len();
+  let mut children : Vec<Vec<(usize, usize)>> = vec![Vec::new(); n];
+  let mut parents : Vec<Vec<usize>> = vec![Vec::new(); n];
+  for (center, satellite, line) in edges {
+    children[ids[center]].push((ids[satellite], *line));
+    parents[ids[satellite]].push(*line);
+  }
+  let mut issues = Vec::new();
+  for (id, lines) in parents.iter().enumerate() {
+    if lines.len() > 1 {
+      issues.push(OrbitIssue::MultipleParents { object: names[id].to_string(), lines: lines.clone() });
+    }
+  }
+
+  // Cycles: depth first walk over all edges, a back edge closes a cycle.
+  // 0 = not seen, 1 = on the current path, 2 = done
+  let mut state = vec![0u8; n];
+  for start in 0..n {
+    if state[start] != 0 {
+      continue;
+    }
+    // (node, next child to visit, line of the edge leading here)
+    let mut stack : Vec<(usize, usize, usize)> = vec![(start, 0, 0)];
+    state[start] = 1;
+    while let Some(top) = stack.last_mut() {
+      let (node, next) = (top.0, top.1);
+      if next == children[node].len() {
+        state[node] = 2;
+        stack.pop();
+        continue;
+      }
+      top.1 += 1;
+      let (child, line) = children[node][next];
+      match state[child] {
+        0 => {
+          state[child] = 1;
+          stack.push((child, 0, line));
+        }
+        1 => {
+          let from = stack.iter().position(|e| e.0 == child).expect("node on the current path");
+          let objects = stack[from..].iter().map(|e| names[e.0].to_string()).collect();
+          let mut lines : Vec<usize> = stack[from+1..].iter().map(|e| e.2).collect();
+          lines.push(line);
+          lines.sort();
+          issues.push(OrbitIssue::Cycle { objects, lines });
+        }
+        _ => {}
+      }
+    }
+  }
+
+  let root_id = match ids.get(root) {
+    Some(id) => *id,
+    None => {
+      issues.push(OrbitIssue::MissingRoot { root: root.to_string() });
+      return issues;
+    }
+  };
+  let reach = |from: usize, seen: &mut Vec<bool>, lines: &mut Vec<usize>| {
+    let mut todo = vec![from];
+    seen[from] = true;
+    let mut size = 0;
+    while let Some(node) = todo.pop() {
+      size += 1;
+      for &(child, line) in &children[node] {
+        lines.push(line);
+        if !seen[child] {
+          seen[child] = true;
+          todo.push(child);
+        }
+      }
+    }
+    size
+  };
+  let mut seen = vec![false; n];
+  reach(root_id, &mut seen, &mut Vec::new());
+  for top in 0..n {
+    if seen[top] || !parents[top].is_empty() {
+      continue;
+    }
+    let mut lines = Vec::new();
+    let size = reach(top, &mut seen, &mut lines);
+    lines.sort();
+    lines.dedup();
+    issues.push(OrbitIssue::Unreachable { object: names[top].to_string(), size, lines });
+  }
+  issues
+}
+
+fn load(data: &str, root: &str) -> Result<OrbitTree, Vec<OrbitIssue>> {
+  let (edges, mut issues) = parse_edges(data);
+  issues.extend(validate(&edges, root));
+  if !issues.is_empty() {
+    return Err(issues);
+  }
+  let edges : Vec<(&str, &str)> = edges.iter().map(|e| (e.0, e.1)).collect();
+  Ok(OrbitTree::new(&edges, root))
 }
 
-fn parse(filename: impl AsRef<std::path::Path>) -> HashMap<String, Node> {
+fn parse(filename: impl AsRef<std::path::Path>, root: &str) -> OrbitTree {
   let data = fs::read_to_string(filename).expect("Something went wrong reading the file");
-  let edges : Vec<(&str, &str)> = data.lines().map(parse_line).collect();
-  let mut tree : HashMap<String, Node> = HashMap::new();
-  for edge in edges {
-    populate_tree(&mut tree, &edge);
+  match load(&data, root) {
+    Ok(tree) => tree,
+    Err(issues) => {
+      let report : Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+      panic!("invalid orbit map:\n{}", report.join("\n"));
+    }
   }
-  return tree;
 }
 
-fn count_orbit(tree : &HashMap<String, Node>, root: &String, depth: usize) -> usize {
-  let mut sum : usize = depth;
-  let childs : &HashSet<String> = &tree.get(root).expect("unknow root").childs;
-  for child in childs {
-    sum = sum + count_orbit(tree, &child, depth + 1)
+fn q1(filename: impl AsRef<std::path::Path>, root: &str) -> usize {
+  parse(filename, root).total_orbits()
+}
+
+// Orbital transfers to go from the object `from` orbits to the object `to`
+// orbits. Measured between the two centers rather than as the distance
+// minus two, so it stays right when one object orbits the other; None when
+// one of them is the root and orbits nothing.
+fn transfers(tree: &OrbitTree, from: NodeId, to: NodeId) -> Option<usize> {
+  Some(tree.distance(tree.parent[from]?, tree.parent[to]?))
+}
+
+fn q2(filename: impl AsRef<std::path::Path>, root: &str) -> usize {
+  let tree = parse(filename, root);
+  let you = tree.id("YOU").expect("YOU not found");
+  let san = tree.id("SAN").expect("SAN not found");
+  transfers(&tree, you, san).expect("YOU and SAN must both orbit something")
+}
+
+#[derive(Debug, Default)]
+struct ExportOptions {
+  // names of two objects whose connecting path is drawn in red
+  highlight: Option<(String, String)>,
+  // replace chains of at least this many single-child objects by one box,
+  // 0 keeps everything
+  collapse: usize,
+}
+
+// What the exporters draw: either an object, or a collapsed chain made of
+// objects that each have exactly one satellite.
+enum ExportNode {
+  Object(NodeId),
+  Chain(Vec<NodeId>),
+}
+
+// Shared walk behind both exporters: the tree as seen after collapsing,
+// with the set of objects lying on the highlighted path.
+struct ExportView<'a> {
+  tree: &'a OrbitTree,
+  options: &'a ExportOptions,
+  on_path: Vec<bool>,
+  ends: Vec<NodeId>,
+}
+
+impl<'a> ExportView<'a> {
+  fn new(tree: &'a OrbitTree, options: &'a ExportOptions) -> ExportView<'a> {
+    let mut on_path = vec![false; tree.names.len()];
+    let mut ends = Vec::new();
+    if let Some((from, to)) = &options.highlight {
+      let from = tree.id(from).unwrap_or_else(|| panic!("{} not found", from));
+      let to = tree.id(to).unwrap_or_else(|| panic!("{} not found", to));
+      for id in tree.path(from, to) {
+        on_path[id] = true;
+      }
+      ends = vec![from, to, tree.lca(from, to)];
+    }
+    ExportView { tree, options, on_path, ends }
+  }
+
+  fn collapsible(&self, id: NodeId) -> bool {
+    self.tree.children[id].len() == 1 && id != self.tree.root && !self.ends.contains(&id)
+  }
+
+  // The satellites of `id` as they are drawn, each with the object the
+  // walk continues from.
+  fn children(&self, id: NodeId) -> Vec<(ExportNode, NodeId)> {
+    self.tree.children[id].iter().map(|&child| {
+      let mut chain = Vec::new();
+      let mut cur = child;
+      while self.collapsible(cur) {
+        chain.push(cur);
+        cur = self.tree.children[cur][0];
+      }
+      if self.options.collapse > 0 && chain.len() >= self.options.collapse {
+        let last = *chain.last().unwrap();
+        (ExportNode::Chain(chain), last)
+      } else {
+        (ExportNode::Object(child), child)
+      }
+    }).collect()
+  }
+
+  fn on_path(&self, node: &ExportNode) -> bool {
+    match node {
+      ExportNode::Object(id) => self.on_path[*id],
+      ExportNode::Chain(ids) => self.on_path[ids[0]],
+    }
+  }
+
+  fn label(&self, node: &ExportNode) -> String {
+    match node {
+      ExportNode::Object(id) => self.tree.name(*id).to_string(),
+      ExportNode::Chain(ids) => format!("{} .. {} ({} objects)", self.tree.name(ids[0]), self.tree.name(*ids.last().unwrap()), ids.len()),
+    }
+  }
+}
+
+// Inside a quoted DOT string: Graphviz knows `\"`, `\\` and `\n` but has
+// no escape for other control characters, so those become spaces.
+fn escape_dot(s: &str) -> String {
+  let mut out = String::new();
+  for c in s.chars() {
+    match c {
+      '"' => out += "\\\"",
+      '\\' => out += "\\\\",
+      '\n' => out += "\\n",
+      c if c.is_control() => out.push(' '),
+      c => out.push(c),
+    }
   }
-  return sum;
+  out
 }
 
-fn q1(filename: impl AsRef<std::path::Path>) -> usize {
-  return count_orbit(&parse(filename), &"COM".to_string(), 0);
+// Inside a JSON string.
+fn escape_json(s: &str) -> String {
+  let mut out = String::new();
+  for c in s.chars() {
+    match c {
+      '"' => out += "\\\"",
+      '\\' => out += "\\\\",
+      '\n' => out += "\\n",
+      '\t' => out += "\\t",
+      c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+      c => out.push(c),
+    }
+  }
+  out
 }
 
-fn extract_path(tree : &HashMap<String, Node>, who: &str) -> HashSet<String> {
-  let mut ret : HashSet<String> = HashSet::new();
-  let mut cur = &who.to_string();
-  ret.insert(cur.to_string());
-  while cur != "COM" {
-    cur = &tree.get(cur).expect("broken tree").parent;
-    ret.insert(cur.to_string());
+// Graphviz DOT, centers pointing to their satellites. Objects are named
+// `n<id>` and collapsed chains `c<id of their first object>`.
+fn to_dot(tree: &OrbitTree, options: &ExportOptions) -> String {
+  let view = ExportView::new(tree, options);
+  let dot_id = |node: &ExportNode| match node {
+    ExportNode::Object(id) => format!("n{}", id),
+    ExportNode::Chain(ids) => format!("c{}", ids[0]),
+  };
+  let mut out = String::from("digraph orbits {\n");
+  let mut todo = vec![ExportNode::Object(tree.root)];
+  let mut from = vec![tree.root];
+  while let (Some(node), Some(cur)) = (todo.pop(), from.pop()) {
+    let mut attrs = format!("label=\"{}\"", escape_dot(&view.label(&node)));
+    if let ExportNode::Chain(_) = node {
+      attrs += ", shape=box, style=dashed";
+    }
+    if view.on_path(&node) {
+      attrs += ", color=red, fontcolor=red";
+    }
+    out += &format!("  {} [{}];\n", dot_id(&node), attrs);
+    let children = view.children(cur);
+    for (child, _) in &children {
+      let style = if view.on_path(&node) && view.on_path(child) { " [color=red, penwidth=2]" } else { "" };
+      out += &format!("  {} -> {}{};\n", dot_id(&node), dot_id(child), style);
+    }
+    for (child, next) in children.into_iter().rev() {
+      todo.push(child);
+      from.push(next);
+    }
   }
-  return ret;
+  out += "}\n";
+  out
 }
 
-fn q2(filename: impl AsRef<std::path::Path>) -> usize {
-  let tree = parse(filename);
-  let hash_you = extract_path(&tree, "YOU");
-  let hash_santa = extract_path(&tree, "SAN");
-  let intersection : Vec<_> = hash_you.intersection(&hash_santa).collect();
-  return hash_you.len() + hash_santa.len() - 2*intersection.len() + 1 - 3;
+// Nested JSON: `{"name": .., "children": [..]}`, with `"path": true` on the
+// highlighted objects and `"collapsed": n` on chains standing for n objects.
+fn to_json(tree: &OrbitTree, options: &ExportOptions) -> String {
+  fn write(view: &ExportView, node: ExportNode, cur: NodeId, out: &mut String) {
+    *out += &format!("{{\"name\":\"{}\"", escape_json(&view.label(&node)));
+    if let ExportNode::Chain(ids) = &node {
+      *out += &format!(",\"collapsed\":{}", ids.len());
+    }
+    if view.on_path(&node) {
+      *out += ",\"path\":true";
+    }
+    *out += ",\"children\":[";
+    for (i, (child, next)) in view.children(cur).into_iter().enumerate() {
+      if i > 0 {
+        out.push(',');
+      }
+      write(view, child, next, out);
+    }
+    *out += "]}";
+  }
+  let view = ExportView::new(tree, options);
+  let mut out = String::new();
+  write(&view, ExportNode::Object(tree.root), tree.root, &mut out);
+  out.push('\n');
+  out
 }
 
+// `advent6 [root] [dot|json]`: with a format, print the map of data.txt
+// with the YOU to SAN path highlighted and chains of 3 or more collapsed.
 fn main() {
-  println!("Question1: {}", q1("data.txt"));
-  println!("Question2: {}", q2("data.txt"));
+  let args : Vec<String> = std::env::args().collect();
+  let root = args.get(1).cloned().unwrap_or_else(|| "COM".to_string());
+  let options = ExportOptions { highlight: Some(("YOU".to_string(), "SAN".to_string())), collapse: 3 };
+  match args.get(2).map(|s| &s[..]) {
+    Some("dot") => print!("{}", to_dot(&parse("data.txt", &root), &options)),
+    Some("json") => print!("{}", to_json(&parse("data.txt", &root), &options)),
+    _ => {
+      println!("Question1: {}", q1("data.txt", &root));
+      println!("Question2: {}", q2("data.txt", &root));
+    }
+  }
 }
 
 #[test]
 fn test_examples1() {
-  assert_eq!(q1("test1.txt"), 42);
+  assert_eq!(q1("test1.txt", "COM"), 42);
 }
 
 #[test]
 fn test_examples2() {
-  assert_eq!(q2("test2.txt"), 4);
-}
\ No newline at end of file
+  assert_eq!(q2("test2.txt", "COM"), 4);
+}
+
+#[test]
+fn test_tree_queries() {
+  let tree = parse("test2.txt", "COM");
+  let id = |name| tree.id(name).unwrap();
+  assert_eq!(tree.depth(id("COM")), 0);
+  assert_eq!(tree.depth(id("YOU")), 7);
+  assert_eq!(tree.name(tree.lca(id("YOU"), id("SAN"))), "D");
+  assert_eq!(tree.name(tree.lca(id("L"), id("K"))), "K");
+  assert_eq!(tree.name(tree.lca(id("H"), id("COM"))), "COM");
+  assert_eq!(tree.distance(id("H"), id("F")), 6);
+  assert_eq!(tree.distance(id("E"), id("E")), 0);
+  assert_eq!(tree.subtree_size(id("COM")), 14);
+  assert_eq!(tree.subtree_size(id("E")), 6);
+  assert_eq!(tree.subtree_size(id("SAN")), 1);
+}
+
+#[test]
+fn test_validation() {
+  assert!(load("COM)B\nB)C\n\n", "COM").is_ok());
+  assert_eq!(load("B)C\nC)D\n", "A").unwrap_err(), vec![OrbitIssue::MissingRoot { root: "A".to_string() }]);
+  assert!(load("B)C\nC)D\n", "B").is_ok());
+
+  let issues = load("COM)B\nB)C\nX)C\nC)D\nD)B\nY)Z\nZ)W\nbroken", "COM").unwrap_err();
+  assert_eq!(issues, vec![
+    OrbitIssue::Malformed { line: 8, text: "broken".to_string() },
+    OrbitIssue::MultipleParents { object: "B".to_string(), lines: vec![1, 5] },
+    OrbitIssue::MultipleParents { object: "C".to_string(), lines: vec![2, 3] },
+    OrbitIssue::Cycle { objects: vec!["B".to_string(), "C".to_string(), "D".to_string()], lines: vec![2, 4, 5] },
+    OrbitIssue::Unreachable { object: "X".to_string(), size: 1, lines: vec![3] },
+    OrbitIssue::Unreachable { object: "Y".to_string(), size: 3, lines: vec![6, 7] },
+  ]);
+  assert_eq!(issues[3].to_string(), "orbit cycle B)C)D (lines 2, 4, 5)");
+}
+
+#[test]
+fn test_export() {
+  let tree = load("COM)B\nB)C\nC)D\nD)E\nB)YOU\nE)SAN\nE)F", "COM").unwrap();
+  assert_eq!(tree.path(tree.id("YOU").unwrap(), tree.id("SAN").unwrap()).len(), 6);
+  let plain = ExportOptions::default();
+  assert_eq!(to_json(&tree, &plain), "{\"name\":\"COM\",\"children\":[{\"name\":\"B\",\"children\":[\
+{\"name\":\"C\",\"children\":[{\"name\":\"D\",\"children\":[{\"name\":\"E\",\"children\":[\
+{\"name\":\"SAN\",\"children\":[]},{\"name\":\"F\",\"children\":[]}]}]}]},{\"name\":\"YOU\",\"children\":[]}]}]}\n");
+  let options = ExportOptions { highlight: Some(("YOU".to_string(), "SAN".to_string())), collapse: 2 };
+  assert_eq!(to_dot(&tree, &options), "digraph orbits {
+  n0 [label=\"COM\"];
+  n0 -> n1;
+  n1 [label=\"B\", color=red, fontcolor=red];
+  n1 -> c2 [color=red, penwidth=2];
+  n1 -> n5 [color=red, penwidth=2];
+  c2 [label=\"C .. D (2 objects)\", shape=box, style=dashed, color=red, fontcolor=red];
+  c2 -> n4 [color=red, penwidth=2];
+  n4 [label=\"E\", color=red, fontcolor=red];
+  n4 -> n6 [color=red, penwidth=2];
+  n4 -> n7;
+  n6 [label=\"SAN\", color=red, fontcolor=red];
+  n7 [label=\"F\"];
+  n5 [label=\"YOU\", color=red, fontcolor=red];
+}
+");
+  assert!(to_json(&tree, &options).contains("{\"name\":\"C .. D (2 objects)\",\"collapsed\":2,\"path\":true,\"children\":[{\"name\":\"E\",\"path\":true,"));
+}
+
+#[test]
+fn test_escape() {
+  let label = "a\"b\\c\nd\u{1}e\tf";
+  assert_eq!(escape_dot(label), "a\\\"b\\\\c\\nd e f");
+  assert_eq!(escape_json(label), "a\\\"b\\\\c\\nd\\u0001e\\tf");
+}
+
+#[test]
+fn test_transfers_adjacent() {
+  let tree = load("COM)A\nA)SAN\nSAN)YOU\n", "COM").unwrap();
+  let (you, san) = (tree.id("YOU").unwrap(), tree.id("SAN").unwrap());
+  assert_eq!(tree.distance(you, san), 1);
+  assert_eq!(transfers(&tree, you, san), Some(1));
+  assert_eq!(transfers(&tree, san, you), Some(1));
+  assert_eq!(transfers(&tree, you, you), Some(0));
+  assert_eq!(transfers(&tree, you, tree.id("COM").unwrap()), None);
+}
@@ -1,5 +1,4 @@
 use std::fs;
-use std::collections::HashSet;
 use std::collections::HashMap;
 use std::collections::VecDeque;
 
@@ -130,82 +129,221 @@ fn oracle2(orig: &Vec<i32>, phases: &Vec<i32>) -> i32 {
   return *output.iter().next().expect("Should not be empty");
 }
 
-fn rec_gen_phases(possible: &HashSet<i32>) -> Vec<Vec<i32>> {
-  let mut ret : Vec<Vec<i32>> = Vec::new();
-  if possible.len() == 1 {
-    let mut new_vec : Vec<i32> = Vec::new();
-    new_vec.push(*possible.iter().next().expect("should not be empty"));
-    ret.push(new_vec);
-  } else {
-    for one in possible {
-      let mut new_possible = possible.clone();
-      new_possible.remove(one);
-      let tmp = rec_gen_phases(&new_possible);
-      for mut t in tmp {
-        t.push(*one);
-        ret.push(t);
+// Lazy permutation generator. Every arrangement is produced in place inside
+// the iterator's own buffer, so walking all of them allocates nothing.
+// Distinct values taken all at once use Heap's algorithm (a single swap per
+// step). Arrangements of k among n, or of values with duplicates, use the
+// lexicographic successor instead: it skips repeated arrangements for free.
+struct Permutations<T> {
+  items: Vec<T>,
+  k: usize,
+  heap: bool,
+  c: Vec<usize>,
+  i: usize,
+  started: bool,
+  done: bool,
+}
+
+impl<T: Ord + Clone> Permutations<T> {
+  fn new(items: Vec<T>) -> Permutations<T> {
+    let k = items.len();
+    Permutations::new_k(items, k)
+  }
+
+  fn new_k(mut items: Vec<T>, k: usize) -> Permutations<T> {
+    let n = items.len();
+    items.sort();
+    let distinct = items.windows(2).all(|w| w[0] != w[1]);
+    Permutations {
+      c: vec![0; n],
+      heap: distinct && k == n,
+      done: k > n,
+      items,
+      k,
+      i: 1,
+      started: false,
+    }
+  }
+
+  // Streaming access: the returned slice is only valid until the next call.
+  fn next_perm(&mut self) -> Option<&[T]> {
+    if self.done {
+      return None;
+    }
+    if !self.started {
+      self.started = true;
+    } else if !self.advance() {
+      self.done = true;
+      return None;
+    }
+    Some(&self.items[..self.k])
+  }
+
+  fn advance(&mut self) -> bool {
+    if self.heap {
+      self.advance_heap()
+    } else {
+      self.advance_lexico()
+    }
+  }
+
+  fn advance_heap(&mut self) -> bool {
+    let n = self.items.len();
+    while self.i < n {
+      if self.c[self.i] < self.i {
+        if self.i & 1 == 0 {
+          self.items.swap(0, self.i);
+        } else {
+          self.items.swap(self.c[self.i], self.i);
+        }
+        self.c[self.i] += 1;
+        self.i = 1;
+        return true;
       }
-    } 
+      self.c[self.i] = 0;
+      self.i += 1;
+    }
+    false
+  }
+
+  fn advance_lexico(&mut self) -> bool {
+    // Reversing the unused tail makes the next permutation of the whole
+    // buffer also the next distinct k-prefix.
+    self.items[self.k..].reverse();
+    let n = self.items.len();
+    if n < 2 {
+      return false;
+    }
+    let mut i = n - 1;
+    while i > 0 && self.items[i-1] >= self.items[i] {
+      i -= 1;
+    }
+    if i == 0 {
+      return false;
+    }
+    let mut j = n - 1;
+    while self.items[j] <= self.items[i-1] {
+      j -= 1;
+    }
+    self.items.swap(i-1, j);
+    self.items[i..].reverse();
+    true
   }
-  return ret;
 }
 
-fn generate_phases(vec_of_possible : Vec<i32>) -> Vec<Vec<i32>> {
-  let mut possible : HashSet<i32> = HashSet::new();
-  for i in vec_of_possible {
-    possible.insert(i);
+impl<T: Ord + Clone> Iterator for Permutations<T> {
+  type Item = Vec<T>;
+
+  fn next(&mut self) -> Option<Vec<T>> {
+    self.next_perm().map(|p| p.to_vec())
   }
-  return rec_gen_phases(&possible);
 }
 
-fn q1(filename: impl AsRef<std::path::Path>) -> i32 {
+struct PhaseSearch {
+  best: Vec<i32>,
+  output: i32,
+  ranking: Vec<(Vec<i32>, i32)>,
+}
+
+// Permutations handed out to the search threads at a time.
+const PHASE_CHUNK : usize = 16;
+
+// Evaluate every phase setting with `oracle`, spread over all available
+// cores. The permutations are walked once, lazily, from a single shared
+// `Permutations`: each thread takes the next `PHASE_CHUNK` of them under
+// the lock and runs the oracle on them outside it. The ranking is sorted
+// by output (best first), then by phases.
+#[allow(clippy::ptr_arg)]
+fn search_phases(orig: &Vec<i32>, possible: Vec<i32>, oracle: fn(&Vec<i32>, &Vec<i32>) -> i32) -> PhaseSearch {
+  let perms = std::sync::Mutex::new(Permutations::new(possible));
+  let nb_threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+  let mut ranking : Vec<(Vec<i32>, i32)> = std::thread::scope(|s| {
+    let handles : Vec<_> = (0..nb_threads).map(|_| {
+      let perms = &perms;
+      s.spawn(move || {
+        let mut found = Vec::new();
+        let mut chunk : Vec<Vec<i32>> = Vec::with_capacity(PHASE_CHUNK);
+        loop {
+          {
+            let mut perms = perms.lock().expect("phase search lock poisoned");
+            while chunk.len() < PHASE_CHUNK {
+              match perms.next_perm() {
+                Some(phases) => chunk.push(phases.to_vec()),
+                None => break,
+              }
+            }
+          }
+          if chunk.is_empty() {
+            return found;
+          }
+          for phases in chunk.drain(..) {
+            let out = oracle(orig, &phases);
+            found.push((phases, out));
+          }
+        }
+      })
+    }).collect();
+    handles.into_iter().flat_map(|h| h.join().expect("phase search thread panicked")).collect()
+  });
+  ranking.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+  let (best, output) = ranking.first().cloned().expect("at least one phase setting");
+  PhaseSearch { best, output, ranking }
+}
+
+fn q1(filename: impl AsRef<std::path::Path>) -> PhaseSearch {
   let orig = parse(filename);
-  let mut best = 0;
-  let all_phases = generate_phases([0,1,2,3,4].to_vec());
-  for phases in all_phases {
-    let out = oracle(&orig,&phases);
-    if out > best {
-      println!("new best {:?} : {}",phases,out);
-      best = out;
-    }
-  }
-  return best;
+  return search_phases(&orig, [0,1,2,3,4].to_vec(), oracle);
 }
 
-fn q2(filename: impl AsRef<std::path::Path>) -> i32 {
+fn q2(filename: impl AsRef<std::path::Path>) -> PhaseSearch {
   let orig = parse(filename);
-  let mut best = 0;
-  let all_phases = generate_phases([5,6,7,8,9].to_vec());
-  for phases in all_phases {
-    let out = oracle2(&orig,&phases);
-    if out > best {
-      println!("new best {:?} : {}",phases,out);
-      best = out;
-    }
-  }
-  return best;
+  return search_phases(&orig, [5,6,7,8,9].to_vec(), oracle2);
 }
 
 fn main() {
-  println!("Question1: {}", q1("data.txt"));
-  println!("Question1: {}", q2("data.txt"));
+  for (name, search) in [("Question1", q1("data.txt")), ("Question2", q2("data.txt"))] {
+    println!("{}: {} (phases {:?}, best of {})", name, search.output, search.best, search.ranking.len());
+  }
 }
 
 #[test]
 fn test_examples1() {
-  assert_eq!(q1("test1.txt"), 43210);
-  assert_eq!(q1("test2.txt"), 54321);
-  assert_eq!(q1("test3.txt"), 65210);
+  assert_eq!(q1("test1.txt").output, 43210);
+  assert_eq!(q1("test2.txt").output, 54321);
+  assert_eq!(q1("test3.txt").output, 65210);
 }
 
 #[test]
 fn test_examples2() {
-  assert_eq!(q2("test4.txt"), 139629729);
-  assert_eq!(q2("test5.txt"), 18216);
+  assert_eq!(q2("test4.txt").output, 139629729);
+  assert_eq!(q2("test5.txt").output, 18216);
 }
 
 #[test]
 fn test_examples3() {
   let orig = parse("test4.txt");
   assert_eq!(oracle2(&orig,&[9,8,7,6,5].to_vec()), 139629729);
-}
\ No newline at end of file
+}
+#[test]
+fn test_permutations() {
+  let all : Vec<Vec<i32>> = Permutations::new([0,1,2,3,4].to_vec()).collect();
+  assert_eq!(all.len(), 120);
+  let unique : std::collections::HashSet<Vec<i32>> = all.iter().cloned().collect();
+  assert_eq!(unique.len(), 120);
+  assert_eq!(Permutations::new_k([1,2,3,4].to_vec(), 2).count(), 12);
+  assert_eq!(Permutations::new_k([1,2].to_vec(), 3).count(), 0);
+  let dup : Vec<Vec<i32>> = Permutations::new([2,1,1].to_vec()).collect();
+  assert_eq!(dup, vec![vec![1,1,2], vec![1,2,1], vec![2,1,1]]);
+  let dup_k : Vec<Vec<i32>> = Permutations::new_k([1,1,2].to_vec(), 2).collect();
+  assert_eq!(dup_k, vec![vec![1,1], vec![1,2], vec![2,1]]);
+}
+
+#[test]
+fn test_search_phases() {
+  let orig = parse("test1.txt");
+  let search = search_phases(&orig, [0,1,2,3,4].to_vec(), oracle);
+  assert_eq!(search.best, [4,3,2,1,0].to_vec());
+  assert_eq!(search.output, 43210);
+  assert_eq!(search.ranking.len(), 120);
+  assert!(search.ranking.windows(2).all(|w| w[0].1 >= w[1].1));
+}
@@ -1,4 +1,7 @@
+#![allow(dead_code)]
 use std::fs;
+use std::io::BufReader;
+use std::io::Read;
 
 #[derive(Debug)]
 struct Layer {
@@ -51,58 +54,329 @@ impl Layer {
       println!("{}", line);
     }
   }
+  // Rows of pixels, lit where the image is white.
+  fn pixels(&self) -> Vec<Vec<bool>> {
+    self.data.chunks(self.width).map(|row| row.iter().map(|d| *d == 1).collect()).collect()
+  }
+  // Digits of the Space Image Format, row after row.
+  fn to_digits(&self) -> String {
+    self.data.iter().map(|d| d.to_string()).collect()
+  }
+  // Plain PBM (P1). Netpbm black is 1, so white pixels (1) become 0 and
+  // everything else, transparent included, is drawn black.
+  fn to_pbm(&self) -> String {
+    let mut out = format!("P1\n{} {}\n", self.width, self.height);
+    for row in self.data.chunks(self.width)
+    {
+      let line : Vec<&str> = row.iter().map(|d| if *d == 1 { "0" } else { "1" }).collect();
+      out += &line.join(" ");
+      out.push('\n');
+    }
+    out
+  }
+  // Plain PGM (P2): black, white, and transparent as a mid grey.
+  fn to_pgm(&self) -> String {
+    let mut out = format!("P2\n{} {}\n{}\n", self.width, self.height, PGM_MAX);
+    for row in self.data.chunks(self.width)
+    {
+      let line : Vec<String> = row.iter().map(|d| PGM_LEVELS[*d as usize].to_string()).collect();
+      out += &line.join(" ");
+      out.push('\n');
+    }
+    out
+  }
+  // Read back a plain PGM written by `to_pgm`.
+  fn from_pgm(text: &str) -> Result<Layer, String> {
+    let mut tokens = text.lines()
+      .map(|l| l.split('#').next().unwrap_or(""))
+      .flat_map(|l| l.split_whitespace());
+    if tokens.next() != Some("P2")
+    {
+      return Err("not a plain PGM file".to_string());
+    }
+    let mut next_number = |what: &str| -> Result<usize, String> {
+      let token = tokens.next().ok_or_else(|| format!("missing {}", what))?;
+      token.parse::<usize>().map_err(|_| format!("bad {}: {}", what, token))
+    };
+    let width = next_number("width")?;
+    let height = next_number("height")?;
+    if next_number("maxval")? != PGM_MAX as usize
+    {
+      return Err(format!("maxval should be {}", PGM_MAX));
+    }
+    let mut data = Vec::new();
+    for _ in 0..width*height
+    {
+      let grey = next_number("pixel")?;
+      match PGM_LEVELS.iter().position(|l| *l as usize == grey) {
+        Some(digit) => data.push(digit as i32),
+        None => return Err(format!("unexpected grey level {}", grey)),
+      }
+    }
+    Ok(Layer { width, height, data })
+  }
 }
 
-fn parse_layers(data: &Vec<i32>, width: usize, height: usize) -> Vec<Layer>
-{
-  let mut ret = Vec::new();
-  let data_length = width*height;
-  let n = data.len() / data_length;
-  for i in 0..n 
+// Grey level of each pixel value: black, white, transparent.
+const PGM_LEVELS : [u8; 3] = [0, 255, 128];
+const PGM_MAX : u8 = 255;
+
+// Write the decoded image as `image.pbm` and `image.pgm`, and every layer
+// as `layer_<n>.pbm` and `layer_<n>.pgm`, in `dir`. PBM only has black and
+// white, so transparent pixels are drawn black there (see `to_pbm`).
+fn export(filename: impl AsRef<std::path::Path>, width: usize, height: usize, dir: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+  let dir = dir.as_ref();
+  fs::create_dir_all(dir)?;
+  let invalid = |e: SifError| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string());
+  let mut img = Layer::transparent(width,height);
+  for (i, layer) in SifDecoder::new(fs::File::open(filename)?, width, height).enumerate()
   {
-    ret.push(Layer {
-      width: width,
-      height: height,
-      data: data[i*data_length..(i+1)*data_length].to_vec()
+    let layer = layer.map_err(invalid)?;
+    img.merge(&layer);
+    fs::write(dir.join(format!("layer_{:03}.pbm", i)), layer.to_pbm())?;
+    fs::write(dir.join(format!("layer_{:03}.pgm", i)), layer.to_pgm())?;
+  }
+  fs::write(dir.join("image.pbm"), img.to_pbm())?;
+  fs::write(dir.join("image.pgm"), img.to_pgm())?;
+  Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum SifError {
+  // byte offset in the input of the bad character
+  InvalidPixel { offset: usize, found: char },
+  // the input ends in the middle of a layer
+  PartialLayer { layer: usize, pixels: usize },
+  Empty,
+  Io(String),
+}
+
+impl std::fmt::Display for SifError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      SifError::InvalidPixel { offset, found } => write!(f, "invalid pixel {:?} at offset {}", found, offset),
+      SifError::PartialLayer { layer, pixels } => write!(f, "layer {} is incomplete: only {} pixels", layer, pixels),
+      SifError::Empty => write!(f, "no layer in the image"),
+      SifError::Io(e) => write!(f, "{}", e),
+    }
+  }
+}
+
+// Reads layers one by one from any reader, so only the layer being read is
+// in memory. Pixels must be 0, 1 or 2; whitespace is only allowed at the
+// very end of the input.
+struct SifDecoder<R: Read> {
+  bytes: std::io::Bytes<BufReader<R>>,
+  width: usize,
+  height: usize,
+  offset: usize,
+  layer: usize,
+  trailing: Option<(usize, char)>,
+  done: bool,
+}
+
+impl<R: Read> SifDecoder<R> {
+  fn new(reader: R, width: usize, height: usize) -> SifDecoder<R> {
+    SifDecoder { bytes: BufReader::new(reader).bytes(), width, height, offset: 0, layer: 0, trailing: None, done: false }
+  }
+
+  fn read_layer(&mut self) -> Result<Option<Layer>, SifError> {
+    let size = self.width * self.height;
+    let mut data = Vec::with_capacity(size);
+    while data.len() < size {
+      let byte = match self.bytes.next() {
+        None => break,
+        Some(byte) => byte.map_err(|e| SifError::Io(e.to_string()))?,
+      };
+      let offset = self.offset;
+      self.offset += 1;
+      match byte {
+        b'0' | b'1' | b'2' => {
+          if let Some((offset, found)) = self.trailing {
+            return Err(SifError::InvalidPixel { offset, found });
+          }
+          data.push((byte - b'0') as i32);
+        }
+        b' ' | b'\t' | b'\r' | b'\n' => {
+          self.trailing.get_or_insert((offset, byte as char));
+        }
+        _ => return Err(SifError::InvalidPixel { offset, found: byte as char }),
+      }
+    }
+    if data.is_empty() {
+      return Ok(None);
+    }
+    if data.len() < size {
+      return Err(SifError::PartialLayer { layer: self.layer, pixels: data.len() });
+    }
+    self.layer += 1;
+    Ok(Some(Layer { width: self.width, height: self.height, data }))
+  }
+}
+
+impl<R: Read> Iterator for SifDecoder<R> {
+  type Item = Result<Layer, SifError>;
+
+  fn next(&mut self) -> Option<Result<Layer, SifError>> {
+    if self.done {
+      return None;
+    }
+    let ret = self.read_layer().transpose();
+    if !matches!(ret, Some(Ok(_))) {
+      self.done = true;
+    }
+    ret
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct LayerStats {
+  index: usize,
+  black: usize,
+  white: usize,
+  transparent: usize,
+  // pixels of the final image this layer decides
+  revealed: usize,
+  // pixels still transparent once this layer is stacked
+  still_transparent: usize,
+}
+
+struct DecodedImage {
+  image: Layer,
+  stats: Vec<LayerStats>,
+}
+
+// Stack the layers as they are read, front layer first, keeping only the
+// composed image and a few counters per layer.
+fn decode<R: Read>(reader: R, width: usize, height: usize) -> Result<DecodedImage, SifError> {
+  let mut image = Layer::transparent(width,height);
+  let mut stats = Vec::new();
+  for layer in SifDecoder::new(reader, width, height) {
+    let layer = layer?;
+    let before = image.count_digit(2) as usize;
+    image.merge(&layer);
+    let after = image.count_digit(2) as usize;
+    stats.push(LayerStats {
+      index: stats.len(),
+      black: layer.count_digit(0) as usize,
+      white: layer.count_digit(1) as usize,
+      transparent: layer.count_digit(2) as usize,
+      revealed: before - after,
+      still_transparent: after,
     });
   }
-  return ret;
+  if stats.is_empty() {
+    return Err(SifError::Empty);
+  }
+  Ok(DecodedImage { image, stats })
 }
 
-fn parse(filename: impl AsRef<std::path::Path>) -> Vec<i32> {
-  return fs::read_to_string(filename).expect("Something went wrong reading the file")
-    .chars().map(|s| s.to_string().parse::<i32>().unwrap()).collect();
+fn decode_file(filename: impl AsRef<std::path::Path>, width: usize, height: usize) -> DecodedImage {
+  let file = fs::File::open(filename).expect("Something went wrong reading the file");
+  decode(file, width, height).unwrap_or_else(|e| panic!("invalid image: {}", e))
 }
 
-fn q1(filename: impl AsRef<std::path::Path>) -> i32 {
-  let data = parse(filename);
-  let layers = parse_layers(&data,25,6);
-  let mut min_0 = 999999;
-  let mut check = 0;
-  for layer in layers
+fn q1(filename: impl AsRef<std::path::Path>, width: usize, height: usize) -> i32 {
+  let decoded = decode_file(filename,width,height);
+  let best = decoded.stats.iter().min_by_key(|s| s.black).expect("at least one layer");
+  (best.white * best.transparent) as i32
+}
+
+fn q2(filename: impl AsRef<std::path::Path>, width: usize, height: usize) -> ocr::Ocr {
+  let img = decode_file(filename,width,height).image;
+  let text = ocr::recognize(&img.pixels());
+  if !text.unknown.is_empty()
   {
-    let nb_0 = layer.count_digit(0);
-    if nb_0 < min_0
-    {
-      min_0 = nb_0;
-      check = layer.count_digit(1) * layer.count_digit(2);
-    }
+    img.print();
   }
-  return check;
+  text
 }
 
-fn q2(filename: impl AsRef<std::path::Path>) -> () {
-  let data = parse(filename);
-  let layers = parse_layers(&data,25,6);
-  let mut img = Layer::transparent(25,6);
-  for layer in layers
+fn print_unknown(text: &ocr::Ocr) {
+  for glyph in &text.unknown
   {
-    img.merge(&layer);
+    eprintln!("unknown glyph {} at column {}:\n{}", glyph.index, glyph.column, glyph.bitmap.join("\n"));
   }
-  img.print();
 }
 
+// `advent8 [width] [height] [output dir]`, the image is 25x6 by default.
 fn main() {
-  println!("{:?}", q1("data.txt"));
-  q2("data.txt");
+  let args : Vec<String> = std::env::args().collect();
+  let width = args.get(1).map(|s| s.parse::<usize>().expect("width should be a number")).unwrap_or(25);
+  let height = args.get(2).map(|s| s.parse::<usize>().expect("height should be a number")).unwrap_or(6);
+  println!("{:?}", q1("data.txt",width,height));
+  let text = q2("data.txt",width,height);
+  println!("Question2: {}", text.text);
+  print_unknown(&text);
+  if let Some(dir) = args.get(3)
+  {
+    export("data.txt",width,height,dir).expect("Something went wrong writing the images");
+  }
+}
+
+#[test]
+fn test_answers() {
+  assert_eq!(q1("data.txt",25,6), 1848);
+  assert_eq!(q2("data.txt",25,6).text, "FGJUZ");
+}
+
+#[test]
+fn test_netpbm() {
+  let layer = Layer { width: 3, height: 2, data: vec![0,1,2,2,1,0] };
+  assert_eq!(layer.to_pbm(), "P1\n3 2\n1 0 1\n1 0 1\n");
+  assert_eq!(layer.to_pgm(), "P2\n3 2\n255\n0 255 128\n128 255 0\n");
+  assert!(Layer::from_pgm("P2\n3 2\n255\n0 255 7\n128 255 0\n").is_err());
+  assert!(Layer::from_pgm("P2\n3 2\n255\n0 255\n").is_err());
+}
+
+#[test]
+fn test_round_trip() {
+  let digits = fs::read_to_string("data.txt").unwrap().trim_end().to_string();
+  let mut encoded = String::new();
+  for layer in SifDecoder::new(digits.as_bytes(), 25, 6)
+  {
+    let layer = layer.unwrap();
+    let decoded = Layer::from_pgm(&format!("# layer\n{}", layer.to_pgm())).unwrap();
+    assert_eq!((decoded.width, decoded.height), (25, 6));
+    encoded += &decoded.to_digits();
+  }
+  assert_eq!(encoded, digits);
+}
+
+#[test]
+fn test_decoder_errors() {
+  let layers : Vec<_> = SifDecoder::new(&b"0222112222120000\n"[..], 2, 2).collect();
+  assert_eq!(layers.len(), 4);
+  assert!(layers.iter().all(|l| l.is_ok()));
+  let decoded = decode(&b"0222112222120000\r\n"[..], 2, 2).unwrap();
+  assert_eq!(decoded.image.data, vec![0,1,1,0]);
+  assert_eq!(decoded.stats[0], LayerStats { index: 0, black: 1, white: 0, transparent: 3, revealed: 1, still_transparent: 3 });
+  assert_eq!((decoded.stats[2].revealed, decoded.stats[2].still_transparent), (1, 1));
+  assert_eq!((decoded.stats[3].revealed, decoded.stats[3].still_transparent), (1, 0));
+  assert_eq!(decode(&b"022211222"[..], 2, 2).err(), Some(SifError::PartialLayer { layer: 2, pixels: 1 }));
+  assert_eq!(decode(&b"02231122"[..], 2, 2).err(), Some(SifError::InvalidPixel { offset: 3, found: '3' }));
+  assert_eq!(decode(&b"0222\n1122"[..], 2, 2).err(), Some(SifError::InvalidPixel { offset: 4, found: '\n' }));
+  assert_eq!(decode(&b"\n"[..], 2, 2).err(), Some(SifError::Empty));
+}
+
+#[test]
+fn test_export() {
+  let dir = std::env::temp_dir().join(format!("advent08_export_{}", std::process::id()));
+  fs::create_dir_all(&dir).unwrap();
+  let input = dir.join("input.txt");
+  fs::write(&input, "0222112222120000\n").unwrap();
+  export(&input, 2, 2, dir.join("out")).unwrap();
+  let mut files : Vec<String> = fs::read_dir(dir.join("out")).unwrap()
+    .map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+  files.sort();
+  assert_eq!(files, vec!["image.pbm", "image.pgm",
+    "layer_000.pbm", "layer_000.pgm", "layer_001.pbm", "layer_001.pgm",
+    "layer_002.pbm", "layer_002.pgm", "layer_003.pbm", "layer_003.pgm"]);
+  let read = |name: &str| fs::read_to_string(dir.join("out").join(name)).unwrap();
+  assert_eq!(read("image.pbm"), "P1\n2 2\n1 0\n0 1\n");
+  assert_eq!(read("image.pgm"), "P2\n2 2\n255\n0 255\n255 0\n");
+  assert_eq!(read("layer_000.pbm"), "P1\n2 2\n1 1\n1 1\n");
+  assert_eq!(read("layer_001.pbm"), "P1\n2 2\n0 0\n1 1\n");
+  assert_eq!(read("layer_001.pgm"), "P2\n2 2\n255\n255 255\n128 128\n");
+  fs::remove_dir_all(&dir).unwrap();
 }
@@ -1,97 +1,244 @@
+#![allow(dead_code)]
 extern crate num;
 
 use std::fs;
 use std::collections::HashSet;
+use std::collections::HashMap;
+use std::cmp::Ordering;
 use num::integer::gcd;
-//use std::collections::HashMap;
 //use std::collections::VecDeque;
 
 type Point = (i32,i32);
 type Field = HashSet<Point>;
 
 
-fn parse(filename: impl AsRef<std::path::Path>) -> Field {
-  let mut field = Field::new();
-  let data = fs::read_to_string(filename).expect("Something went wrong reading the file");
-  let mut y = 0;
-  for line in data.lines() {
-    let mut x = 0;
-    for chr in line.chars() {
-      if chr == '#' { field.insert((x, y)); }
-      x = x + 1;
+// Characters of an asteroid map. The station is optional and also stands
+// on an asteroid, like the `X` of the puzzle examples.
+#[derive(Debug, Clone, Copy)]
+struct MapSymbols {
+  empty: char,
+  asteroid: char,
+  station: char,
+}
+
+impl Default for MapSymbols {
+  fn default() -> MapSymbols {
+    MapSymbols { empty: '.', asteroid: '#', station: 'X' }
+  }
+}
+
+#[derive(Debug)]
+struct AsteroidMap {
+  field: Field,
+  station: Option<Point>,
+  width: i32,
+  height: i32,
+}
+
+#[derive(Debug, PartialEq)]
+enum MapError {
+  // line and column count from 1
+  InvalidChar { line: usize, column: usize, found: char },
+  SecondStation { line: usize, column: usize },
+  RaggedLine { line: usize, width: usize, expected: usize },
+}
+
+impl std::fmt::Display for MapError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      MapError::InvalidChar { line, column, found } => write!(f, "line {}, column {}: unexpected {:?}", line, column, found),
+      MapError::SecondStation { line, column } => write!(f, "line {}, column {}: second station", line, column),
+      MapError::RaggedLine { line, width, expected } => write!(f, "line {}: {} cells, expected {}", line, width, expected),
     }
-    y = y + 1;
   }
-  return field;
 }
 
-fn is_empty(field: &Field, p: &Point) -> bool {
-  return !field.contains(p);
+fn parse_map(data: &str, symbols: &MapSymbols) -> Result<AsteroidMap, MapError> {
+  let mut map = AsteroidMap { field: Field::new(), station: None, width: 0, height: 0 };
+  // Only line endings and trailing empty lines go: the empty symbol may
+  // itself be whitespace.
+  let mut lines : Vec<&str> = data.lines().map(|l| l.trim_end_matches('\r')).collect();
+  while lines.last() == Some(&"") {
+    lines.pop();
+  }
+  for (y, line) in lines.iter().enumerate() {
+    let width = line.chars().count();
+    if y == 0 {
+      map.width = width as i32;
+    } else if width != map.width as usize {
+      return Err(MapError::RaggedLine { line: y + 1, width, expected: map.width as usize });
+    }
+    for (x, chr) in line.chars().enumerate() {
+      let p = (x as i32, y as i32);
+      if chr == symbols.asteroid {
+        map.field.insert(p);
+      } else if chr == symbols.station {
+        if map.station.is_some() {
+          return Err(MapError::SecondStation { line: y + 1, column: x + 1 });
+        }
+        map.station = Some(p);
+        map.field.insert(p);
+      } else if chr != symbols.empty {
+        return Err(MapError::InvalidChar { line: y + 1, column: x + 1, found: chr });
+      }
+    }
+  }
+  map.height = lines.len() as i32;
+  Ok(map)
 }
 
-fn has_los(field: &Field, from: &Point, to: &Point) -> bool {
+fn parse_file(filename: impl AsRef<std::path::Path>) -> AsteroidMap {
+  let data = fs::read_to_string(filename).expect("Something went wrong reading the file");
+  parse_map(&data, &MapSymbols::default()).unwrap_or_else(|e| panic!("invalid asteroid map: {}", e))
+}
+
+fn parse(filename: impl AsRef<std::path::Path>) -> Field {
+  parse_file(filename).field
+}
+
+// Direction from `from` to `to` as the smallest integer vector, so every
+// asteroid hiding behind another one gets the exact same direction.
+fn direction(from: &Point, to: &Point) -> Point {
   let diff = (to.0 - from.0, to.1 - from.1);
   let n = gcd(diff.0,diff.1);
-  //println!("from:{:?} to:{:?} n:{:?} diff:{:?}", from, to, n, diff);
-  for i in 1..n {
-    let p = (from.0 + i * diff.0 / n, from.1 + i * diff.1 / n);
-    //println!("{:?}", p);
-    if !is_empty(field, &p) { return false; }
-  }
-  return true;
+  (diff.0 / n, diff.1 / n)
 }
 
+// Clockwise order of directions, starting straight up (y grows downward).
+// Directions are first split in two halves, [up, down) on the right and
+// [down, up) on the left; inside a half the cross product tells which one
+// comes first. No float is involved.
+fn cmp_angle(a: &Point, b: &Point) -> Ordering {
+  let half = |p: &Point| if p.0 > 0 || (p.0 == 0 && p.1 < 0) { 0 } else { 1 };
+  half(a).cmp(&half(b)).then_with(|| {
+    let cross = a.0 as i64 * b.1 as i64 - a.1 as i64 * b.0 as i64;
+    0.cmp(&cross)
+  })
+}
+
+fn count_visible(field: &Field, from: &Point) -> usize {
+  field.iter().filter(|to| *to != from).map(|to| direction(from, to)).collect::<HashSet<Point>>().len()
+}
+
+// Each asteroid sees one asteroid per distinct direction: O(n) per
+// candidate, O(n²) overall. Ties go to the smallest (x, y).
 fn get_best_pos(field : &Field) -> (i32, Point) {
-  let mut best_count = 0;
-  let mut best_pos = (0,0);
-  for from in field {
-    let mut count = 0;
-    for to in field {
-      if has_los(&field, &from, &to) { count = count + 1; }
-    }
-    if best_count < count {
-      best_count = count;
-      best_pos = *from;
+  let mut candidates : Vec<&Point> = field.iter().collect();
+  candidates.sort();
+  let mut best = (0, (0,0));
+  for from in candidates {
+    let count = count_visible(field, from) as i32;
+    if best.0 < count {
+      best = (count, *from);
     }
   }
-  return (best_count - 1, best_pos);
+  best
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Vaporized {
+  pos: Point,
+  // 1 for the first turn of the laser
+  rotation: usize,
+  // reduced direction from the station
+  direction: Point,
+}
+
+impl Vaporized {
+  // Clockwise angle from straight up, in degrees, for display only.
+  fn degrees(&self) -> f64 {
+    let (dx, dy) = (self.direction.0 as f64, self.direction.1 as f64);
+    let deg = dx.atan2(-dy).to_degrees();
+    if deg < 0.0 { deg + 360.0 } else { deg }
+  }
+}
+
+// Asteroids in the order the laser destroys them. They are grouped by
+// direction once, each group sorted by distance, and the laser takes the
+// next asteroid of every group at each turn.
+struct VaporizationOrder {
+  // directions in clockwise order, closest asteroid last so it pops first
+  groups: Vec<(Point, Vec<Point>)>,
+  rotation: usize,
+  next_group: usize,
+  remaining: usize,
 }
 
-fn get_los_asteroids(field: &Field, source: &Point) -> Field {
-  //println!("collecting");
-  let mut asteroids = Field::new();
-  for to in field {
-    if has_los(field, source, &to) { 
-      asteroids.insert(*to); 
+impl Iterator for VaporizationOrder {
+  type Item = Vaporized;
+
+  fn next(&mut self) -> Option<Vaporized> {
+    while self.remaining > 0 {
+      if self.next_group == self.groups.len() {
+        self.next_group = 0;
+        self.rotation += 1;
+      }
+      let (direction, targets) = &mut self.groups[self.next_group];
+      self.next_group += 1;
+      if let Some(pos) = targets.pop() {
+        self.remaining -= 1;
+        return Some(Vaporized { pos, rotation: self.rotation, direction: *direction });
+      }
     }
+    None
   }
-  return asteroids;
 }
 
-fn compute_angle(from: &Point, to: &Point) -> f64 {
-  //println!("compute {:?} {:?}", from, to);
-  let diff = ((to.0 - from.0) as f64, (to.1 - from.1) as f64);
-  let rayon = (diff.0*diff.0 + diff.1*diff.1).sqrt();
-  let angle = (diff.1/rayon).asin();
-  //println!("angle {}", angle);
-  if diff.0 < 0.0 {
-    return std::f64::consts::PI - angle
+fn vaporization_order(field: &Field, station: &Point) -> VaporizationOrder {
+  let mut groups : HashMap<Point, Vec<Point>> = HashMap::new();
+  for to in field.iter().filter(|to| *to != station) {
+    groups.entry(direction(station, to)).or_default().push(*to);
+  }
+  let dist = |p: &Point| (p.0 - station.0).abs() + (p.1 - station.1).abs();
+  let mut groups : Vec<(Point, Vec<Point>)> = groups.into_iter().collect();
+  for (_, targets) in groups.iter_mut() {
+    targets.sort_by_key(|p| std::cmp::Reverse(dist(p)));
   }
-  return angle;
+  groups.sort_by(|a, b| cmp_angle(&a.0, &b.0));
+  let remaining = groups.iter().map(|g| g.1.len()).sum();
+  VaporizationOrder { groups, rotation: 1, next_group: 0, remaining }
+}
+
+// Visible asteroids from every asteroid, `None` on empty cells, row by row.
+fn visibility_map(map: &AsteroidMap) -> Vec<Vec<Option<usize>>> {
+  (0..map.height).map(|y| (0..map.width).map(|x| {
+    if map.field.contains(&(x,y)) { Some(count_visible(&map.field, &(x,y))) } else { None }
+  }).collect()).collect()
 }
 
-fn order_by_angle(asteroids: &Field, source: &Point) -> Vec<Point> {
-  //println!("ordering");
-  let mut ret : Vec<(&Point, f64)> = asteroids.iter().map(|i| (i,compute_angle(source, i))).collect();
-  //println!("the vector: {:?}", ret);
-  ret.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-  //println!("done ordering");
-  return ret.iter().map(|t| *t.0).collect();
+// Counts right aligned in columns as wide as the largest one; the station,
+// when the map has one, is marked with a `*` after its count.
+fn heatmap_text(map: &AsteroidMap) -> String {
+  let counts = visibility_map(map);
+  let max = counts.iter().flatten().flatten().max().cloned().unwrap_or(0);
+  let width = max.to_string().len();
+  let mut out = String::new();
+  for (y, row) in counts.iter().enumerate() {
+    let cells : Vec<String> = row.iter().enumerate().map(|(x, c)| {
+      let mark = if map.station == Some((x as i32, y as i32)) { "*" } else { " " };
+      match c {
+        Some(n) => format!("{:>w$}{}", n, mark, w = width),
+        None => format!("{:>w$}{}", ".", mark, w = width),
+      }
+    }).collect();
+    out += cells.concat().trim_end();
+    out.push('\n');
+  }
+  out
 }
 
-fn remove_asteroids(field: &mut Field, asteroids: &Field) {
-  //println!("removing");
-  for ast in asteroids { field.remove(ast); }
+// Plain PGM (P2), one pixel per cell: the count of visible asteroids, the
+// brightest being the best place for the station. Empty cells are black.
+fn heatmap_pgm(map: &AsteroidMap) -> String {
+  let counts = visibility_map(map);
+  let max = counts.iter().flatten().flatten().max().cloned().unwrap_or(0).max(1);
+  let mut out = format!("P2\n{} {}\n{}\n", map.width, map.height, max);
+  for row in counts {
+    let cells : Vec<String> = row.iter().map(|c| c.unwrap_or(0).to_string()).collect();
+    out += &cells.join(" ");
+    out.push('\n');
+  }
+  out
 }
 
 fn q1(filename: impl AsRef<std::path::Path>) -> i32 {
@@ -100,26 +247,22 @@ fn q1(filename: impl AsRef<std::path::Path>) -> i32 {
 }
 
 fn q2(filename: impl AsRef<std::path::Path>, index: i32) -> i32 {
-  let mut field = parse(filename);
+  let field = parse(filename);
   let station = get_best_pos(&field).1;
-  field.remove(&station);
-  let mut total_destroyed : i32 = 0;
-  while field.len() > 0 {
-    let asteroids = get_los_asteroids(&field, &station);
-    if total_destroyed + (asteroids.len() as i32) > (index-1) {
-      let ordered = order_by_angle(&asteroids, &station);
-      let pt = ordered[(index - 1 - total_destroyed) as usize];
-      return pt.0 * 100 + pt.1;
-    }
-    remove_asteroids(&mut field, &asteroids);
-    total_destroyed = total_destroyed + (asteroids.len() as i32);
-  }
-  panic!("not expected to finish before 200 asteroids destroyed");
+  let pt = vaporization_order(&field, &station).nth(index as usize - 1).expect("not enough asteroids").pos;
+  pt.0 * 100 + pt.1
 }
 
+// `advent10 [heatmap.pgm]`: also print the visibility heatmap of data.txt
+// and save it as a PGM image.
 fn main() {
   println!("Question1: {}", q1("data.txt"));
   println!("Question2: {}", q2("data.txt",200));
+  if let Some(output) = std::env::args().nth(1) {
+    let map = parse_file("data.txt");
+    print!("{}", heatmap_text(&map));
+    fs::write(output, heatmap_pgm(&map)).expect("Something went wrong writing the heatmap");
+  }
 }
 
 #[test]
@@ -195,4 +338,62 @@ fn test_q2_examples201() {
 #[test]
 fn test_q2_examples299() {
   assert_eq!(q2("test4.txt", 299), 1101);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_cmp_angle() {
+  let mut dirs : Vec<Point> = vec![(-1,-1), (0,1), (1,0), (-1,0), (0,-1), (1,1), (-1,1), (1,-1), (1000,-999), (999,-1000)];
+  dirs.sort_by(cmp_angle);
+  assert_eq!(dirs, vec![(0,-1), (999,-1000), (1,-1), (1000,-999), (1,0), (1,1), (0,1), (-1,1), (-1,0), (-1,-1)]);
+  assert_eq!(direction(&(1,1), &(7,-8)), (2,-3));
+}
+
+#[test]
+fn test_vaporization_order() {
+  let field = parse("test4.txt");
+  let order : Vec<Vaporized> = vaporization_order(&field, &(11,13)).collect();
+  assert_eq!(order.len(), field.len() - 1);
+  assert_eq!(order[0], Vaporized { pos: (11,12), rotation: 1, direction: (0,-1) });
+  assert_eq!(order[0].degrees(), 0.0);
+  assert_eq!(order[199].pos, (8,2));
+  assert_eq!(order.last().unwrap().pos, (11,1));
+  assert!(order.windows(2).all(|w| w[0].rotation <= w[1].rotation));
+  let mut field = Field::new();
+  field.insert((0,0));
+  field.insert((0,1));
+  field.insert((1,1));
+  // the station does not need to be an asteroid
+  let order : Vec<(Point, usize)> = vaporization_order(&field, &(0,2)).map(|v| (v.pos, v.rotation)).collect();
+  assert_eq!(order, vec![((0,1), 1), ((1,1), 1), ((0,0), 2)]);
+}
+
+#[test]
+fn test_parse_map() {
+  let map = parse_map(".#..#\n.....\n###X#\n....#\n...##\n\n", &MapSymbols::default()).unwrap();
+  assert_eq!(map.station, Some((3,2)));
+  assert_eq!(map.field.len(), 10);
+  assert_eq!((map.width, map.height), (5, 5));
+  assert_eq!(parse_map(".#\n#?", &MapSymbols::default()).unwrap_err(), MapError::InvalidChar { line: 2, column: 2, found: '?' });
+  assert_eq!(parse_map("X#\n#X", &MapSymbols::default()).unwrap_err(), MapError::SecondStation { line: 2, column: 2 });
+  assert_eq!(parse_map(".#\n#", &MapSymbols::default()).unwrap_err(), MapError::RaggedLine { line: 2, width: 1, expected: 2 });
+  let symbols = MapSymbols { empty: ' ', asteroid: 'o', station: 'S' };
+  assert_eq!(parse_map(" o\nSo", &symbols).unwrap().station, Some((0,1)));
+}
+
+#[test]
+fn test_heatmap() {
+  let map = parse_map(".#..#\n.....\n#####\n....#\n...X#", &MapSymbols::default()).unwrap();
+  assert_eq!(heatmap_text(&map), ". 7 . . 7\n. . . . .\n6 7 7 7 5\n. . . . 7\n. . . 8*7\n");
+  assert_eq!(heatmap_pgm(&map), "P2\n5 5\n8\n0 7 0 0 7\n0 0 0 0 0\n6 7 7 7 5\n0 0 0 0 7\n0 0 0 8 7\n");
+}
+
+#[test]
+fn test_parse_map_blank_symbol() {
+  let symbols = MapSymbols { empty: ' ', asteroid: 'o', station: 'S' };
+  let map = parse_map("o \r\nSo\n  \n\n\n", &symbols).unwrap();
+  assert_eq!((map.width, map.height), (2, 3));
+  assert_eq!(map.station, Some((0, 1)));
+  assert_eq!(map.field.len(), 3);
+  assert_eq!(parse_map("o \nSo", &symbols).unwrap().height, 2);
+  assert!(matches!(parse_map("o\nSo", &symbols), Err(MapError::RaggedLine { line: 2, width: 2, expected: 1 })));
+}
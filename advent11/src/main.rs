@@ -156,50 +156,286 @@ fn print_grid(grid : &Grid) {
   print_grid_minmax(&grid, min_x, max_x, min_y, max_y);
 }
 
-fn robot(state : &mut State, initial_color : i32) -> Grid {
-  let mut grid = Grid::new();
-  let mut pos : Point = (0,0);
-  grid.insert(pos, initial_color);
-  let mut dir = 0; // 0 up, 1 right, 2 down, 3 left
-  while !state.finished {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Heading {
+  Up,
+  Right,
+  Down,
+  Left,
+}
+
+impl Heading {
+  fn turn_right(self) -> Heading {
+    match self {
+      Heading::Up => Heading::Right,
+      Heading::Right => Heading::Down,
+      Heading::Down => Heading::Left,
+      Heading::Left => Heading::Up,
+    }
+  }
+
+  fn turn_left(self) -> Heading {
+    self.turn_right().turn_right().turn_right()
+  }
+
+  // The robot outputs 0 to turn left and 1 to turn right.
+  fn turn(self, movement: i64) -> Heading {
+    match movement {
+      0 => self.turn_left(),
+      1 => self.turn_right(),
+      _ => panic!("unexpected turn {}", movement),
+    }
+  }
+
+  // y grows downward, as in `print_grid`
+  fn step(self, pos: Point) -> Point {
+    match self {
+      Heading::Up => (pos.0, pos.1 - 1),
+      Heading::Right => (pos.0 + 1, pos.1),
+      Heading::Down => (pos.0, pos.1 + 1),
+      Heading::Left => (pos.0 - 1, pos.1),
+    }
+  }
+
+  fn symbol(self) -> char {
+    match self {
+      Heading::Up => '^',
+      Heading::Right => '>',
+      Heading::Down => 'v',
+      Heading::Left => '<',
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RobotEvent {
+  Paint { pos: Point, color: i32 },
+  // the robot turns on `pos`, then moves one panel toward `heading`
+  Turn { pos: Point, heading: Heading },
+}
+
+// Hull painting robot driven by an Intcode brain. Every instruction it
+// follows is kept in `events`, so the run can be replayed afterward.
+struct PainterRobot {
+  state: State,
+  initial_color: i32,
+  grid: Grid,
+  pos: Point,
+  heading: Heading,
+  events: Vec<RobotEvent>,
+}
+
+impl PainterRobot {
+  fn new(state: State, initial_color: i32) -> PainterRobot {
+    let mut grid = Grid::new();
+    grid.insert((0,0), initial_color);
+    PainterRobot { state, initial_color, grid, pos: (0,0), heading: Heading::Up, events: Vec::new() }
+  }
+
+  // One paint and move; false once the program has halted.
+  fn step(&mut self) -> bool {
+    if self.state.finished {
+      return false;
+    }
     let mut input : VecDeque<i64> = VecDeque::new();
-    input.push_back(*grid.get(&pos).unwrap_or(&0) as i64);
-    let output = state.process(&mut input);
+    input.push_back(*self.grid.get(&self.pos).unwrap_or(&0) as i64);
+    let output = self.state.process(&mut input);
+    if output.is_empty() && self.state.finished {
+      return false;
+    }
     let mut output_iter = output.iter();
-    let color = output_iter.next().expect("should output one color");
-    let movement = output_iter.next().expect("should output one move");
-    grid.insert(pos, *color as i32);
-    dir = (4 + dir + (movement * 2) - 1) % 4;
-    match dir {
-      0 => pos.1 = pos.1 - 1,
-      1 => pos.0 = pos.0 + 1,
-      2 => pos.1 = pos.1 + 1,
-      3 => pos.0 = pos.0 - 1,
-      _ => panic!("can't be, I've put a modulo")
+    let color = *output_iter.next().expect("should output one color") as i32;
+    let movement = *output_iter.next().expect("should output one move");
+    self.grid.insert(self.pos, color);
+    self.events.push(RobotEvent::Paint { pos: self.pos, color });
+    self.heading = self.heading.turn(movement);
+    self.events.push(RobotEvent::Turn { pos: self.pos, heading: self.heading });
+    self.pos = self.heading.step(self.pos);
+    true
+  }
+
+  fn run(&mut self) -> &Grid {
+    while self.step() {}
+    &self.grid
+  }
+
+  // Number of times each panel received paint, whatever the color.
+  fn paint_counts(&self) -> HashMap<Point, usize> {
+    let mut counts = HashMap::new();
+    for event in &self.events {
+      if let RobotEvent::Paint { pos, .. } = event {
+        *counts.entry(*pos).or_insert(0) += 1;
+      }
     }
-    /*
-    println!("*********************");
-    print_grid_minmax(&grid, -10, 10, -10, 10);
-    println!("count {} move {} color {}", grid.len(), movement, color);
-    */
+    counts
+  }
+
+  // Smallest box holding every panel the robot painted or stood on.
+  fn bounds(&self) -> (Point, Point) {
+    let mut min = (0, 0);
+    let mut max = (0, 0);
+    for p in self.grid.keys().chain(std::iter::once(&self.pos)) {
+      min = (min.0.min(p.0), min.1.min(p.1));
+      max = (max.0.max(p.0), max.1.max(p.1));
+    }
+    (min, max)
+  }
+
+  // Replay the recorded events, calling `frame` with the hull and the robot
+  // before the first move and after every `every` moves (and after the last).
+  fn replay(&self, every: usize, mut frame: impl FnMut(usize, &Grid, Point, Heading)) {
+    let every = every.max(1);
+    let mut grid = Grid::new();
+    grid.insert((0,0), self.initial_color);
+    let (mut pos, mut heading) = ((0,0), Heading::Up);
+    let mut moves = 0;
+    frame(0, &grid, pos, heading);
+    for event in &self.events {
+      match *event {
+        RobotEvent::Paint { pos: p, color } => { grid.insert(p, color); }
+        RobotEvent::Turn { pos: p, heading: h } => {
+          heading = h;
+          pos = h.step(p);
+          moves += 1;
+          if moves % every == 0 {
+            frame(moves, &grid, pos, heading);
+          }
+        }
+      }
+    }
+    if moves % every != 0 {
+      frame(moves, &grid, pos, heading);
+    }
+  }
+
+  // Text animation: `frame <n> after <moves> moves` then the hull, `#` for
+  // white, `.` for black and the robot as `^>v<`.
+  fn ascii_frames(&self, every: usize) -> String {
+    let (min, max) = self.bounds();
+    let mut out = String::new();
+    let mut n = 0;
+    self.replay(every, |moves, grid, pos, heading| {
+      out += &format!("frame {} after {} moves\n", n, moves);
+      for y in min.1..=max.1 {
+        let line : String = (min.0..=max.0).map(|x| {
+          if (x,y) == pos { heading.symbol() } else if *grid.get(&(x,y)).unwrap_or(&0) == 1 { '#' } else { '.' }
+        }).collect();
+        out += &line;
+        out.push('\n');
+      }
+      n += 1;
+    });
+    out
+  }
+
+  // Plain PBM images, one per frame. White panels are white, black panels
+  // black, and the robot's panel is drawn inverted.
+  fn pbm_frames(&self, every: usize) -> Vec<String> {
+    let (min, max) = self.bounds();
+    let mut frames = Vec::new();
+    self.replay(every, |_, grid, pos, _| {
+      let mut pbm = format!("P1\n{} {}\n", max.0 - min.0 + 1, max.1 - min.1 + 1);
+      for y in min.1..=max.1 {
+        let line : Vec<&str> = (min.0..=max.0).map(|x| {
+          let white = *grid.get(&(x,y)).unwrap_or(&0) == 1;
+          if white != ((x,y) == pos) { "0" } else { "1" }
+        }).collect();
+        pbm += &line.join(" ");
+        pbm.push('\n');
+      }
+      frames.push(pbm);
+    });
+    frames
   }
-  return grid;
+}
+
+// Write `frames.txt` and `frame_<n>.pbm` for one frame every `every` moves.
+fn export_animation(robot: &PainterRobot, every: usize, dir: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+  let dir = dir.as_ref();
+  fs::create_dir_all(dir)?;
+  fs::write(dir.join("frames.txt"), robot.ascii_frames(every))?;
+  for (i, pbm) in robot.pbm_frames(every).iter().enumerate() {
+    fs::write(dir.join(format!("frame_{:05}.pbm", i)), pbm)?;
+  }
+  Ok(())
+}
+
+fn robot(state : State, initial_color : i32) -> Grid {
+  let mut robot = PainterRobot::new(state, initial_color);
+  robot.run();
+  robot.grid
 }
 
 fn q1(filename: impl AsRef<std::path::Path>) -> usize {
-  let mut state = State::new_from_file(filename);
-  let grid = robot(&mut state,0);
-  print_grid(&grid);
-  return grid.len();
+  let mut robot = PainterRobot::new(State::new_from_file(filename), 0);
+  print_grid(robot.run());
+  robot.paint_counts().len()
+}
+
+// Rows of the painted area's bounding box, lit on white panels.
+fn grid_pixels(grid : &Grid) -> Vec<Vec<bool>> {
+  let min_x = grid.keys().map(|p| p.0).min().unwrap_or(0);
+  let max_x = grid.keys().map(|p| p.0).max().unwrap_or(0);
+  let min_y = grid.keys().map(|p| p.1).min().unwrap_or(0);
+  let max_y = grid.keys().map(|p| p.1).max().unwrap_or(0);
+  (min_y..=max_y).map(|y| (min_x..=max_x).map(|x| *grid.get(&(x,y)).unwrap_or(&0) == 1).collect()).collect()
 }
 
-fn q2(filename: impl AsRef<std::path::Path>) {
-  let mut state = State::new_from_file(filename);
-  let grid = robot(&mut state,1);
-  print_grid(&grid);
+fn q2(filename: impl AsRef<std::path::Path>) -> ocr::Ocr {
+  let state = State::new_from_file(filename);
+  let grid = robot(state,1);
+  let text = ocr::recognize(&grid_pixels(&grid));
+  if !text.unknown.is_empty() {
+    print_grid(&grid);
+  }
+  text
 }
 
+// `advent11 [output dir] [every]`: also save the animation of the second
+// run, one frame every `every` moves (10 by default).
 fn main() {
   println!("Question1: {}", q1("data.txt"));
-  q2("data.txt");
+  let text = q2("data.txt");
+  println!("Question2: {}", text.text);
+  for glyph in &text.unknown {
+    eprintln!("unknown glyph {} at column {}:\n{}", glyph.index, glyph.column, glyph.bitmap.join("\n"));
+  }
+  let args : Vec<String> = std::env::args().collect();
+  if let Some(dir) = args.get(1) {
+    let every = args.get(2).map(|s| s.parse::<usize>().expect("every should be a number")).unwrap_or(10);
+    let mut robot = PainterRobot::new(State::new_from_file("data.txt"), 1);
+    robot.run();
+    export_animation(&robot, every, dir).expect("Something went wrong writing the animation");
+  }
+}
+
+#[test]
+fn test_answers() {
+  assert_eq!(q1("data.txt"), 2336);
+  assert_eq!(q2("data.txt").text, "UZAEKBLP");
+}
+
+#[test]
+fn test_painter_robot() {
+  // the example of the puzzle: paint/turn pairs answered whatever the input
+  let mut code = Vec::new();
+  for (color, turn) in [(1,0), (0,0), (1,0), (1,0), (0,1), (1,0), (1,0)].iter() {
+    code.extend_from_slice(&[3,100,104,*color,104,*turn]);
+  }
+  code.push(99);
+  let mut robot = PainterRobot::new(State::new_from_vector(&code), 0);
+  robot.run();
+  assert_eq!(robot.grid.len(), 6);
+  assert_eq!((robot.pos, robot.heading), ((0,-1), Heading::Left));
+  assert_eq!(robot.events.len(), 14);
+  assert_eq!(robot.events[1], RobotEvent::Turn { pos: (0,0), heading: Heading::Left });
+  let counts = robot.paint_counts();
+  assert_eq!(counts[&(0,0)], 2);
+  assert_eq!(counts.len(), 6);
+  let frames = robot.ascii_frames(1);
+  assert_eq!(frames.matches("frame ").count(), 8);
+  assert!(frames.ends_with("frame 7 after 7 moves\n.<#\n..#\n##.\n"));
+  let pbm = robot.pbm_frames(4);
+  assert_eq!(pbm.len(), 3);
+  assert_eq!(pbm[0], "P1\n3 3\n1 1 1\n1 0 1\n1 1 1\n");
 }
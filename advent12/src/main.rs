@@ -1,94 +1,110 @@
+#![allow(dead_code)]
+
 extern crate regex;
 extern crate num;
 
 use std::fs;
 use std::fmt;
 use regex::Regex;
-use num::integer::lcm;
+use num::integer::gcd;
 
-type Point = [i32; 3];
+// Coordinates along each axis of the system, in the order of `System::axes`.
+type Point = Vec<i64>;
 
 struct Moon {
   position: Point,
   velocity: Point
 }
 
-impl fmt::Display for Moon {
-  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-      write!(f, "pos=<x={} ,y={}, z={}>, vel=<x={} ,y={}, z={}>", 
-        self.position[0], self.position[1], self.position[2],
-        self.velocity[0], self.velocity[1], self.velocity[2])
-  }
-}
-
 struct System {
+  axes: Vec<String>,
   moons: Vec<Moon>
 }
 
+fn write_vector(f: &mut fmt::Formatter, axes: &[String], values: &[i64]) -> fmt::Result {
+  let parts : Vec<String> = axes.iter().zip(values).map(|(a, v)| format!("{}={}", a, v)).collect();
+  write!(f, "<{}>", parts.join(", "))
+}
+
 impl fmt::Display for System {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     for moon in &self.moons {
-      writeln!(f, "{}", moon).unwrap();
+      write!(f, "pos=")?;
+      write_vector(f, &self.axes, &moon.position)?;
+      write!(f, ", vel=")?;
+      write_vector(f, &self.axes, &moon.velocity)?;
+      writeln!(f)?;
     }
-    return fmt::Result::Ok(());
+    Ok(())
   }
 }
 
-fn diff_clamp(a:i32,b:i32) -> i32 {
-  let diff = a-b;
-  if diff >= 1 { return 1; }
-  if diff <= -1 { return -1; }
-  return 0;
+fn diff_clamp(a:i64,b:i64) -> i64 {
+  (a-b).signum()
 }
 
-fn pos_diff_clamp(a:&Point, b:&Point) -> Point {
-  return [
-    diff_clamp(a[0],b[0]),
-    diff_clamp(a[1],b[1]),
-    diff_clamp(a[2],b[2])
-  ];
+// One `<name=value, ...>` line: the axis names and the position.
+fn parse_line(line: &str) -> Result<(Vec<String>, Point), String> {
+  let outer = Regex::new(r"^\s*<(.*)>\s*$").unwrap();
+  let axis = Regex::new(r"^\s*([A-Za-z_][A-Za-z0-9_]*)\s*=\s*(-?\d+)\s*$").unwrap();
+  let inner = outer.captures(line).ok_or(format!("expected <name=value, ...>, found {:?}", line))?;
+  let mut names = Vec::new();
+  let mut position = Vec::new();
+  for part in inner[1].split(',') {
+    let caps = axis.captures(part).ok_or(format!("expected name=value, found {:?}", part.trim()))?;
+    names.push(caps[1].to_string());
+    position.push(caps[2].parse::<i64>().map_err(|e| format!("{}: {}", &caps[2], e))?);
+  }
+  Ok((names, position))
 }
 
 impl Moon {
-  fn new_from_line(line: &String) -> Moon {
-    let re = Regex::new(r"^<x=([^,]+), y=([^,]+), z=([^,]+)>$").unwrap();
-    let caps = re.captures(line).unwrap();
-    return Moon {
-      position: [caps[1].parse::<i32>().unwrap(),caps[2].parse::<i32>().unwrap(),caps[3].parse::<i32>().unwrap()],
-      velocity: [0,0,0]
-    }
+  fn new(position: Point) -> Moon {
+    let velocity = vec![0; position.len()];
+    Moon { position, velocity }
   }
-  fn compute_kinetic_energy(&self) -> i32 {
-    return self.velocity.iter().fold(0, |a,v| a + v.abs());
+  fn compute_kinetic_energy(&self) -> i64 {
+    self.velocity.iter().map(|v| v.abs()).sum()
   }
-  fn compute_potential_energy(&self) -> i32 {
-    return self.position.iter().fold(0, |a,p| a + p.abs());
+  fn compute_potential_energy(&self) -> i64 {
+    self.position.iter().map(|p| p.abs()).sum()
   }
-  fn compute_energy(&self) -> i32 {
-    return self.compute_potential_energy() * self.compute_kinetic_energy();
+  fn compute_energy(&self) -> i64 {
+    self.compute_potential_energy() * self.compute_kinetic_energy()
   }
   fn apply_gravity(&mut self, other : &mut Moon){
-    let diff = pos_diff_clamp(&self.position, &other.position);
-    self.velocity[0] = self.velocity[0] - diff[0];
-    self.velocity[1] = self.velocity[1] - diff[1];
-    self.velocity[2] = self.velocity[2] - diff[2];
-    other.velocity[0] = other.velocity[0] + diff[0];
-    other.velocity[1] = other.velocity[1] + diff[1];
-    other.velocity[2] = other.velocity[2] + diff[2];
+    for d in 0..self.position.len() {
+      let diff = diff_clamp(self.position[d], other.position[d]);
+      self.velocity[d] -= diff;
+      other.velocity[d] += diff;
+    }
   }
   fn update(&mut self){
-    self.position[0] = self.position[0] + self.velocity[0];
-    self.position[1] = self.position[1] + self.velocity[1];
-    self.position[2] = self.position[2] + self.velocity[2];
+    for (p, v) in self.position.iter_mut().zip(&self.velocity) {
+      *p += v;
+    }
   }
 }
 
 impl System {
+  // Every line must name the same axes in the same order; there can be any
+  // number of them.
+  fn parse(data: &str) -> Result<System, String> {
+    let mut system = System { axes: Vec::new(), moons: Vec::new() };
+    for (i, line) in data.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+      let (names, position) = parse_line(line).map_err(|e| format!("line {}: {}", i + 1, e))?;
+      if system.moons.is_empty() {
+        system.axes = names;
+      } else if names != system.axes {
+        return Err(format!("line {}: axes {} differ from {}", i + 1, names.join(","), system.axes.join(",")));
+      }
+      system.moons.push(Moon::new(position));
+    }
+    Ok(system)
+  }
   fn new_from_file(filename: impl AsRef<std::path::Path>) -> System {
     let data = fs::read_to_string(filename).expect("Something went wrong reading the file");
-    return System {
-      moons: data.lines().map(|s| Moon::new_from_line(&s.to_string())).collect()
-    }
+    System::parse(&data).unwrap_or_else(|e| panic!("invalid moon system: {}", e))
   }
   fn update(&mut self) {
     let n = self.moons.len();
@@ -102,74 +118,185 @@ impl System {
       moon.update();
     }
   }
-  fn compute_energy(&self) -> i32 {
-    return self.moons.iter().fold(0, |a,m| a + m.compute_energy());
+  fn compute_energy(&self) -> i64 {
+    self.moons.iter().map(|m| m.compute_energy()).sum()
   }
-  fn compute_kinetic_energy(&self) -> i32 {
-    return self.moons.iter().fold(0, |a,m| a + m.compute_kinetic_energy());
+  fn compute_kinetic_energy(&self) -> i64 {
+    self.moons.iter().map(|m| m.compute_kinetic_energy()).sum()
+  }
+  // Runs `nb_step` steps and returns the states seen, initial one included.
+  fn record(&mut self, nb_step: usize) -> Recorder {
+    let mut recorder = Recorder::new(&self.axes);
+    recorder.capture(0, self);
+    for step in 1..=nb_step {
+      self.update();
+      recorder.capture(step, self);
+    }
+    recorder
+  }
+  fn axis_state(&self, idx: usize) -> AxisState {
+    AxisState {
+      position: self.moons.iter().map(|m| m.position[idx]).collect(),
+      velocity: self.moons.iter().map(|m| m.velocity[idx]).collect(),
+    }
   }
-  fn hash(&self,idx: usize) -> String {
-    let p : Vec<String> = self.moons.iter().map(|m| m.position[idx].to_string()).collect();
-    let v : Vec<String> = self.moons.iter().map(|m| m.velocity[idx].to_string()).collect();
-    return format!("{}_{}",p.join(","),v.join(","));
+  // Number of steps before the whole system is back to its current state,
+  // None if it does not fit in a u64. Axes do not interact, so each one is
+  // simulated on its own thread and the periods are combined with a LCM.
+  fn period(&self) -> Option<u64> {
+    let axes : Vec<AxisState> = (0..self.axes.len()).map(|idx| self.axis_state(idx)).collect();
+    let periods : Vec<u64> = std::thread::scope(|s| {
+      let handles : Vec<_> = axes.iter().map(|axis| s.spawn(move || axis.period())).collect();
+      handles.into_iter().map(|h| h.join().expect("axis simulation panicked")).collect()
+    });
+    periods.into_iter().try_fold(1u64, checked_lcm)
   }
 }
 
-fn q1(filename: impl AsRef<std::path::Path>, nb_step : usize) -> i32{
-  let mut system = System::new_from_file(filename);
-  //println!("{}", system);
-  for step in 0..nb_step {
-    system.update();
-    //println!("step:{} energy:{}", step+1, system.compute_energy());
-    //println!("{}", system);
+// Positions and velocities of every moon along a single axis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AxisState {
+  position: Vec<i64>,
+  velocity: Vec<i64>,
+}
+
+impl AxisState {
+  fn update(&mut self) {
+    let n = self.position.len();
+    for i in 0..n {
+      for j in (i+1)..n {
+        let diff = diff_clamp(self.position[i], self.position[j]);
+        self.velocity[i] -= diff;
+        self.velocity[j] += diff;
+      }
+    }
+    for (p, v) in self.position.iter_mut().zip(&self.velocity) {
+      *p += v;
+    }
+  }
+  // A step can be undone (positions minus velocities, then gravity
+  // removed), so states never merge: the motion is a pure cycle and the
+  // first state seen twice is the starting one. Nothing needs storing.
+  fn period(&self) -> u64 {
+    let mut state = self.clone();
+    let mut steps = 0;
+    loop {
+      state.update();
+      steps += 1;
+      if state == *self {
+        return steps;
+      }
+    }
+  }
+}
+
+// Per moon values captured by a `Recorder` at one step.
+#[derive(Debug, Clone, PartialEq)]
+struct MoonSample {
+  position: Point,
+  velocity: Point,
+  kinetic: i64,
+  potential: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct StepRecord {
+  step: usize,
+  moons: Vec<MoonSample>,
+}
+
+impl StepRecord {
+  fn total_kinetic(&self) -> i64 {
+    self.moons.iter().map(|m| m.kinetic).sum()
+  }
+  fn total_energy(&self) -> i64 {
+    self.moons.iter().map(|m| m.kinetic * m.potential).sum()
   }
-  return system.compute_energy();
 }
 
+// Time series of a simulation, one record per step starting with the
+// initial state (step 0).
+struct Recorder {
+  axes: Vec<String>,
+  steps: Vec<StepRecord>,
+}
 
+impl Recorder {
+  fn new(axes: &[String]) -> Recorder {
+    Recorder { axes: axes.to_vec(), steps: Vec::new() }
+  }
+  fn capture(&mut self, step: usize, system: &System) {
+    let moons = system.moons.iter().map(|m| MoonSample {
+      position: m.position.clone(),
+      velocity: m.velocity.clone(),
+      kinetic: m.compute_kinetic_energy(),
+      potential: m.compute_potential_energy(),
+    }).collect();
+    self.steps.push(StepRecord { step, moons });
+  }
+  // Steps at which every moon is still: in a reversible system these are
+  // the turning points, half way through a cycle.
+  fn zero_kinetic_steps(&self) -> Vec<usize> {
+    self.steps.iter().filter(|r| r.total_kinetic() == 0).map(|r| r.step).collect()
+  }
+  // One row per moon and step:
+  // step,moon,pos_<axis>...,vel_<axis>...,kinetic,potential,energy
+  fn write_csv<W: std::io::Write>(&self, out: &mut W) -> std::io::Result<()> {
+    let mut header = vec!["step".to_string(), "moon".to_string()];
+    header.extend(self.axes.iter().map(|a| format!("pos_{}", a)));
+    header.extend(self.axes.iter().map(|a| format!("vel_{}", a)));
+    header.extend(["kinetic", "potential", "energy"].iter().map(|s| s.to_string()));
+    writeln!(out, "{}", header.join(","))?;
+    for record in &self.steps {
+      for (i, m) in record.moons.iter().enumerate() {
+        let mut row = vec![record.step.to_string(), i.to_string()];
+        row.extend(m.position.iter().chain(&m.velocity).map(|v| v.to_string()));
+        row.push(m.kinetic.to_string());
+        row.push(m.potential.to_string());
+        row.push((m.kinetic * m.potential).to_string());
+        writeln!(out, "{}", row.join(","))?;
+      }
+    }
+    Ok(())
+  }
+}
+
+fn checked_lcm(a: u64, b: u64) -> Option<u64> {
+  if a == 0 || b == 0 {
+    return Some(0);
+  }
+  (a / gcd(a, b)).checked_mul(b)
+}
 
-fn q2(filename: impl AsRef<std::path::Path>) -> i64{
+fn q1(filename: impl AsRef<std::path::Path>, nb_step : usize) -> i64{
   let mut system = System::new_from_file(filename);
-  let hash_x = system.hash(0);
-  let hash_y = system.hash(1);
-  let hash_z = system.hash(2);
-  let mut step_x = 0;
-  let mut step_y = 0;
-  let mut step_z = 0;
-  let mut step = 0;
-  while step_x == 0 || step_y == 0 || step_z == 0 {
+  //println!("{}", system);
+  for step in 0..nb_step {
     system.update();
     //println!("step:{} energy:{}", step+1, system.compute_energy());
     //println!("{}", system);
-    /*
-    if system.compute_kinetic_energy() == 0 {
-      println!("{}", system);
-    }*/
-    step = step + 1;
-    if step_x == 0 && hash_x == system.hash(0) {
-      println!("match x ! {}", step);
-      step_x = step;
-    }
-    if step_y == 0 && hash_y == system.hash(1) {
-      println!("match y ! {}", step);
-      step_y = step;
-    }
-    if step_z == 0 && hash_z == system.hash(2) {
-      println!("match z ! {}", step);
-      step_z = step;
-    }
-    if step % 1000000 == 0 {
-      println!("step: {}", step);
-    }
   }
-  return lcm(lcm(step_x,step_y),step_z);
+  return system.compute_energy();
 }
 
 
 
+fn q2(filename: impl AsRef<std::path::Path>) -> u64{
+  System::new_from_file(filename).period().expect("period overflows a u64")
+}
+
+// advent12 [csv] [steps]: optionally dumps the first steps to a CSV file.
 fn main() {
     println!("Question1: {}", q1("data.txt",1000));
     println!("Question2: {}", q2("data.txt"));
+    let args : Vec<String> = std::env::args().collect();
+    if let Some(path) = args.get(1) {
+      let nb_step = args.get(2).map(|s| s.parse().expect("steps must be a number")).unwrap_or(1000);
+      let recorder = System::new_from_file("data.txt").record(nb_step);
+      let mut file = std::io::BufWriter::new(fs::File::create(path).expect("cannot create csv file"));
+      recorder.write_csv(&mut file).expect("cannot write csv file");
+      println!("Recorded {} steps to {}, still at steps {:?}", nb_step, path, recorder.zero_kinetic_steps());
+    }
 }
 
 #[test]
@@ -191,3 +318,44 @@ fn test_q2_examples1() {
 fn test_q2_examples2() {
   assert_eq!(q2("test2.txt"), 4686774924);
 }
+
+#[test]
+fn test_parse_axes() {
+  let system = System::parse("<w=1, x=-2,y=3 , z=0>\n<w=0, x=0, y=0, z=4>\n\n").unwrap();
+  assert_eq!(system.axes, vec!["w", "x", "y", "z"]);
+  assert_eq!(system.moons[0].position, vec![1, -2, 3, 0]);
+  assert_eq!(format!("{}", system).lines().next(), Some("pos=<w=1, x=-2, y=3, z=0>, vel=<w=0, x=0, y=0, z=0>"));
+  assert_eq!(System::parse("<x=1>\n<y=2>").err(), Some("line 2: axes y differ from x".to_string()));
+  assert_eq!(System::parse("<x=1>\nx=2").err(), Some("line 2: expected <name=value, ...>, found \"x=2\"".to_string()));
+  let mut plane = System::parse("<a=0, b=5>\n<a=3, b=5>").unwrap();
+  plane.update();
+  assert_eq!(plane.moons[0].position, vec![1, 5]);
+  assert_eq!(plane.compute_energy(), 6 + 7);
+}
+
+#[test]
+fn test_axis_period() {
+  let system = System::new_from_file("test1.txt");
+  let periods : Vec<u64> = (0..3).map(|idx| system.axis_state(idx).period()).collect();
+  assert_eq!(periods, vec![18, 28, 44]);
+  assert_eq!(checked_lcm(18, 28), Some(252));
+  assert_eq!(checked_lcm(u64::MAX, 2), None);
+  assert_eq!(checked_lcm(u64::MAX, u64::MAX), Some(u64::MAX));
+}
+
+#[test]
+fn test_recorder() {
+  let mut system = System::new_from_file("test1.txt");
+  let recorder = system.record(2772);
+  assert_eq!(recorder.steps.len(), 2773);
+  assert_eq!(recorder.steps[10].total_energy(), 179);
+  assert_eq!(recorder.steps[1].moons[0].velocity, vec![3, -1, -1]);
+  assert_eq!(recorder.zero_kinetic_steps(), vec![0, 1386, 2772]);
+  let mut csv = Vec::new();
+  recorder.write_csv(&mut csv).unwrap();
+  let csv = String::from_utf8(csv).unwrap();
+  let lines : Vec<&str> = csv.lines().collect();
+  assert_eq!(lines[0], "step,moon,pos_x,pos_y,pos_z,vel_x,vel_y,vel_z,kinetic,potential,energy");
+  assert_eq!(lines[5], "1,0,2,-1,1,3,-1,-1,5,4,20");
+  assert_eq!(lines.len(), 1 + 2773 * 4);
+}
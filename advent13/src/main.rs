@@ -1,7 +1,11 @@
+#![allow(dead_code)]
+
 use std::fs;
+use std::fmt;
 //use std::collections::HashSet;
 use std::collections::HashMap;
 use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
 
 #[derive(Debug)]
 struct State {
@@ -141,90 +145,727 @@ impl State {
   }
 }
 
-fn print_grid_minmax(grid : &Grid, min_x: i32, max_x: i32, min_y:i32, max_y:i32) {
-  for y in min_y..(max_y+1) {
-    let mut line = Vec::new();
-    for x in min_x..(max_x+1) {
-      line.push(*grid.get(&(x,y)).unwrap_or(&' '));
-    }
-    let line_str : String = line.into_iter().collect();
-    println!("{}", line_str);
+fn tile(t: i64) -> char {
+  match t {
+    0 => ' ',
+    1 => '#',
+    2 => 'H',
+    3 => '_',
+    4 => 'o',
+    _ => panic!("unexpected type")
   }
 }
 
-fn print_grid(grid : &Grid) {
+fn tile_code(c: char) -> i64 {
+  match c {
+    ' ' => 0,
+    '#' => 1,
+    'H' => 2,
+    '_' => 3,
+    'o' => 4,
+    _ => panic!("unexpected tile {:?}", c)
+  }
+}
+
+fn render_grid(grid : &Grid) -> String {
   let min_x = grid.keys().fold(99999, |a,i| a.min(i.0));
   let min_y = grid.keys().fold(99999, |a,i| a.min(i.1));
   let max_x = grid.keys().fold(-99999, |a,i| a.max(i.0));
   let max_y = grid.keys().fold(-99999, |a,i| a.max(i.1));
-  print_grid_minmax(&grid, min_x, max_x, min_y, max_y);
-}
-
-fn game(state : &mut State) -> (Grid,i64) {
-  let mut grid = Grid::new();
-  let mut pos_ball : Point = (0,0);
-  let mut pos_pad : Point = (0,0);
-  let mut score = 0;
-  while !state.finished {
-    let mut input : VecDeque<i64> = VecDeque::new();
-    if pos_ball.0 < pos_pad.0 {
-      input.push_back(-1);
-    } else if pos_ball.0 > pos_pad.0 {
-      input.push_back(1);
-    } else {
-      input.push_back(0);
-    }
-    let output = state.process(&mut input);
-    let mut output_iter = output.iter();
-    loop {
-      let tmp = output_iter.next();
-      if !tmp.is_some() { break }
-      let x = *tmp.expect("should output X") as i32;
-      let y = *output_iter.next().expect("should output Y") as i32;
-      let t = output_iter.next().expect("should output Type");
-      if x == -1 && y == 0 {
-        score = *t;
-      } else {
-        let c = match t {
-          0 => ' ',
-          1 => '#',
-          2 => 'H',
-          3 => '_',
-          4 => 'o',
-          _ => panic!("unexpected type")
-        };
-        if c == 'o' {
-          pos_ball = (x,y);
-        }
-        if c == '_' {
-          pos_pad = (x,y);
-        }
-        grid.insert((x,y), c);
+  let mut out = String::new();
+  for y in min_y..(max_y+1) {
+    for x in min_x..(max_x+1) {
+      out.push(*grid.get(&(x,y)).unwrap_or(&' '));
+    }
+    out.push('\n');
+  }
+  out
+}
+
+// The cabinet: the Intcode program plus what it has drawn so far. The
+// program is run up to its next joystick read, so the screen is always
+// up to date when a move has to be chosen.
+struct Arcade {
+  state: State,
+  grid: Grid,
+  score: i64,
+  pos_ball: Point,
+  pos_pad: Point,
+  inputs: Vec<i64>, // every joystick position sent, in order
+}
+
+impl Arcade {
+  fn new(state: State) -> Arcade {
+    let mut arcade = Arcade {
+      state,
+      grid: Grid::new(),
+      score: 0,
+      pos_ball: (0,0),
+      pos_pad: (0,0),
+      inputs: Vec::new(),
+    };
+    arcade.run(VecDeque::new());
+    arcade
+  }
+
+  fn run(&mut self, mut input: VecDeque<i64>) {
+    let output = self.state.process(&mut input);
+    self.draw_all(output);
+  }
+
+  fn draw_all(&mut self, mut output: VecDeque<i64>) {
+    for chunk in output.make_contiguous().chunks(3) {
+      self.draw(chunk);
+    }
+  }
+
+  fn draw(&mut self, chunk: &[i64]) {
+    let (x, y, t) = match chunk {
+      [x, y, t] => (*x as i32, *y as i32, *t),
+      _ => panic!("output should come by triplets"),
+    };
+    if x == -1 && y == 0 {
+      self.score = t;
+      return;
+    }
+    let c = tile(t);
+    if c == 'o' {
+      self.pos_ball = (x,y);
+    }
+    if c == '_' {
+      self.pos_pad = (x,y);
+    }
+    self.grid.insert((x,y), c);
+  }
+
+  // Moves the joystick (-1 left, 0 neutral, 1 right) for one frame.
+  fn step(&mut self, joystick: i64) {
+    self.inputs.push(joystick);
+    self.run(VecDeque::from(vec![joystick]));
+  }
+
+  fn finished(&self) -> bool {
+    self.state.finished
+  }
+
+  fn blocks(&self) -> usize {
+    self.grid.values().filter(|c| **c == 'H').count()
+  }
+
+  fn render(&self) -> String {
+    format!("Score: {}\n{}", self.score, render_grid(&self.grid))
+  }
+
+  // Whole session as text, one `key values` line per field. Memory is
+  // written as addr:value pairs and tiles as x,y,type triplets, the way
+  // the program outputs them.
+  fn save(&self) -> String {
+    let mut mem : Vec<(&usize,&i64)> = self.state.mem.iter().collect();
+    mem.sort();
+    let mem : Vec<String> = mem.iter().map(|(a,v)| format!("{}:{}", a, v)).collect();
+    let mut tiles : Vec<(&Point,&char)> = self.grid.iter().collect();
+    tiles.sort_by_key(|(p,_)| (p.1, p.0));
+    let tiles : Vec<String> = tiles.iter().map(|(p,c)| format!("{},{},{}", p.0, p.1, tile_code(**c))).collect();
+    let mut out = String::new();
+    out += &format!("ip {}\n", self.state.ip);
+    out += &format!("rb {}\n", self.state.rb);
+    out += &format!("finished {}\n", self.state.finished);
+    out += &format!("score {}\n", self.score);
+    out += &format!("ball {},{}\n", self.pos_ball.0, self.pos_ball.1);
+    out += &format!("paddle {},{}\n", self.pos_pad.0, self.pos_pad.1);
+    out += &format!("mem {}\n", mem.join(","));
+    out += &format!("tiles {}\n", tiles.join(","));
+    out += &format!("inputs {}\n", recording_to_string(&self.inputs));
+    out
+  }
+
+  fn load(data: &str) -> Result<Arcade, String> {
+    let mut fields = HashMap::new();
+    for (i, line) in data.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+      let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+      if fields.insert(key, value.trim()).is_some() {
+        return Err(format!("line {}: duplicate field {}", i + 1, key));
+      }
+    }
+    let field = |key: &str| fields.get(key).copied().ok_or(format!("missing field {}", key));
+    let number = |key: &str| field(key)?.parse::<i64>().map_err(|e| format!("{}: {}", key, e));
+    let point = |key: &str| -> Result<Point, String> {
+      match parse_values(field(key)?).map_err(|e| format!("{}: {}", key, e))?[..] {
+        [x, y] => Ok((x as i32, y as i32)),
+        _ => Err(format!("{}: expected x,y", key)),
       }
-      /*
-      println!("*********************");
-      print_grid_minmax(&grid, -10, 10, -10, 10);
-      println!("count {} move {} color {}", grid.len(), movement, color);
-      */
+    };
+    let mut mem = HashMap::new();
+    for pair in field("mem")?.split(',').filter(|p| !p.is_empty()) {
+      let (addr, val) = pair.split_once(':').ok_or(format!("mem: expected addr:value, found {:?}", pair))?;
+      let addr = addr.parse::<usize>().map_err(|e| format!("mem: {}", e))?;
+      mem.insert(addr, val.parse::<i64>().map_err(|e| format!("mem: {}", e))?);
+    }
+    let state = State {
+      ip: number("ip")? as usize,
+      rb: number("rb")?,
+      mem,
+      finished: field("finished")?.parse::<bool>().map_err(|e| format!("finished: {}", e))?,
+    };
+    let mut arcade = Arcade {
+      state,
+      grid: Grid::new(),
+      score: number("score")?,
+      pos_ball: point("ball")?,
+      pos_pad: point("paddle")?,
+      inputs: parse_values(field("inputs")?).map_err(|e| format!("inputs: {}", e))?,
+    };
+    let tiles = parse_values(field("tiles")?).map_err(|e| format!("tiles: {}", e))?;
+    if tiles.len() % 3 != 0 || tiles.chunks(3).any(|t| !(0..5).contains(&t[2])) {
+      return Err("tiles: expected x,y,type triplets".to_string());
+    }
+    for (i, t) in tiles.chunks(3).enumerate() {
+      if (t[0], t[1]) == (-1, 0) {
+        return Err(format!("tiles: triplet {} is the score display", i));
+      }
+      arcade.grid.insert((t[0] as i32, t[1] as i32), tile(t[2]));
+    }
+    Ok(arcade)
+  }
+}
+
+// A joystick sequence together with the score it is expected to reach.
+struct Replay {
+  inputs: Vec<i64>,
+  score: i64,
+}
+
+impl fmt::Display for Replay {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "score {}", self.score)?;
+    writeln!(f, "inputs {}", recording_to_string(&self.inputs))
+  }
+}
+
+impl Replay {
+  // What has been played on `arcade` since its `start`-th input.
+  fn record(arcade: &Arcade, start: usize) -> Replay {
+    Replay { inputs: arcade.inputs[start..].to_vec(), score: arcade.score }
+  }
+
+  fn parse(data: &str) -> Result<Replay, String> {
+    let mut score = None;
+    let mut inputs = None;
+    for line in data.lines().filter(|l| !l.trim().is_empty()) {
+      match line.split_once(' ').unwrap_or((line, "")) {
+        ("score", v) => score = Some(v.trim().parse::<i64>().map_err(|e| format!("score: {}", e))?),
+        ("inputs", v) => inputs = Some(parse_values(v).map_err(|e| format!("inputs: {}", e))?),
+        (key, _) => return Err(format!("unknown field {}", key)),
+      }
+    }
+    Ok(Replay {
+      inputs: inputs.ok_or("missing field inputs")?,
+      score: score.ok_or("missing field score")?,
+    })
+  }
+
+  // Plays the inputs on `arcade` and checks the score reached. The program
+  // has no randomness, so a replay from the same start always ends the
+  // same way.
+  fn run(&self, arcade: &mut Arcade) -> Result<(), String> {
+    for (i, joystick) in self.inputs.iter().enumerate() {
+      if arcade.finished() {
+        return Err(format!("game over after {} of {} inputs", i, self.inputs.len()));
+      }
+      arcade.step(*joystick);
+    }
+    if arcade.score != self.score {
+      return Err(format!("replay reached score {} instead of {}", arcade.score, self.score));
+    }
+    Ok(())
+  }
+}
+
+// Keys understood by the interactive mode; anything else is ignored.
+fn key_to_joystick(key: char) -> Option<i64> {
+  match key {
+    'a' | 'h' | '4' | '<' => Some(-1),
+    's' | 'j' | '5' | '.' => Some(0),
+    'd' | 'l' | '6' | '>' => Some(1),
+    _ => None,
+  }
+}
+
+// Recordings are stored like Intcode programs: comma separated values.
+fn recording_to_string(inputs: &[i64]) -> String {
+  let parts : Vec<String> = inputs.iter().map(|i| i.to_string()).collect();
+  parts.join(",")
+}
+
+fn parse_values(data: &str) -> Result<Vec<i64>, String> {
+  data.split(',').map(|s| s.trim()).filter(|s| !s.is_empty())
+    .map(|s| s.parse::<i64>().map_err(|e| format!("{:?}: {}", s, e)))
+    .collect()
+}
+
+// Puts the terminal in cbreak mode while alive, so each keypress reaches
+// the program at once, without echo and without waiting for Enter. Goes
+// through `stty` rather than a terminal crate; if that fails the terminal
+// stays line buffered and keys are only seen after Enter.
+struct CbreakMode {
+  active: bool,
+}
+
+impl CbreakMode {
+  fn enter() -> CbreakMode {
+    let status = std::process::Command::new("stty").args(["-icanon", "-echo", "min", "1"])
+      .stderr(std::process::Stdio::null()).status();
+    CbreakMode { active: status.map(|s| s.success()).unwrap_or(false) }
+  }
+}
+
+impl Drop for CbreakMode {
+  fn drop(&mut self) {
+    if self.active {
+      let _ = std::process::Command::new("stty").args(["icanon", "echo"]).status();
+    }
+  }
+}
+
+// Plays with the keys read one byte at a time from `keys`, each key being
+// one frame; other bytes, newlines included, are ignored. With `display`
+// the screen is redrawn to `out` after every frame, so the same loop serves
+// the terminal and the tests. 'k' keeps the session in `slot` and 'r' goes
+// back to it, even once the game is over. Stops on 'q' or at the end of the
+// input.
+fn play<R: BufRead, W: Write>(arcade: &mut Arcade, keys: R, out: &mut W, display: bool, slot: &mut Option<String>) -> io::Result<()> {
+  if display {
+    write!(out, "\x1b[2J\x1b[H{}", arcade.render())?;
+    out.flush()?;
+  }
+  for byte in keys.bytes() {
+    let key = byte? as char;
+    if key == 'q' {
+      return Ok(());
+    }
+    let moved = match key {
+      'k' => { *slot = Some(arcade.save()); false }
+      'r' => match slot {
+        Some(saved) => { *arcade = Arcade::load(saved).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?; true }
+        None => false,
+      },
+      _ => match key_to_joystick(key) {
+        Some(joystick) if !arcade.finished() => { arcade.step(joystick); true }
+        _ => false,
+      },
+    };
+    if moved && display {
+      write!(out, "\x1b[2J\x1b[H{}", arcade.render())?;
+      out.flush()?;
+    }
+  }
+  Ok(())
+}
+
+// Chooses the joystick position for the next frame.
+trait Controller {
+  fn name(&self) -> &str;
+  fn joystick(&mut self, arcade: &Arcade) -> i64;
+}
+
+// Keeps the paddle under the ball.
+struct FollowBall;
+
+impl Controller for FollowBall {
+  fn name(&self) -> &str { "follow" }
+  fn joystick(&mut self, arcade: &Arcade) -> i64 {
+    (arcade.pos_ball.0 - arcade.pos_pad.0).signum() as i64
+  }
+}
+
+// Works out where the ball will reach the row above the paddle, bouncing
+// off the side walls, and waits there. Blocks are ignored: the guess is
+// made again every frame, so a bounce on a block only moves the target.
+#[derive(Default)]
+struct Predictive {
+  last_ball: Option<Point>,
+}
+
+impl Predictive {
+  fn target(&self, arcade: &Arcade) -> i32 {
+    let (x, y) = arcade.pos_ball;
+    let (dx, dy) = match self.last_ball {
+      Some((lx, ly)) => (x - lx, y - ly),
+      None => (0, 0),
+    };
+    if dy <= 0 || dx == 0 {
+      return x;
+    }
+    // The playing area is between the wall columns.
+    let lo = arcade.grid.keys().map(|p| p.0).min().unwrap_or(0) + 1;
+    let hi = arcade.grid.keys().map(|p| p.0).max().unwrap_or(0) - 1;
+    if hi <= lo {
+      return x;
+    }
+    let steps = (arcade.pos_pad.1 - 1 - y).max(0);
+    let period = 2 * (hi - lo);
+    let unfolded = (x - lo + dx * steps).rem_euclid(period);
+    lo + if unfolded > hi - lo { period - unfolded } else { unfolded }
+  }
+}
+
+impl Controller for Predictive {
+  fn name(&self) -> &str { "predictive" }
+  fn joystick(&mut self, arcade: &Arcade) -> i64 {
+    let target = self.target(arcade);
+    self.last_ball = Some(arcade.pos_ball);
+    (target - arcade.pos_pad.0).signum() as i64
+  }
+}
+
+// What happened during a game played by a controller.
+#[derive(Debug, Clone, PartialEq)]
+struct GameStats {
+  controller: String,
+  frames: usize,
+  moves: usize,       // frames with the joystick off center
+  score: i64,
+  blocks: Vec<usize>, // blocks left before the first frame and after each one
+}
+
+impl GameStats {
+  fn blocks_broken(&self) -> usize {
+    self.blocks.first().unwrap_or(&0) - self.blocks.last().unwrap_or(&0)
+  }
+  fn won(&self) -> bool {
+    self.blocks.last() == Some(&0)
+  }
+  fn frames_per_block(&self) -> f64 {
+    self.frames as f64 / self.blocks_broken().max(1) as f64
+  }
+  fn score_per_block(&self) -> f64 {
+    self.score as f64 / self.blocks_broken().max(1) as f64
+  }
+}
+
+impl fmt::Display for GameStats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}: {} score {} in {} frames ({} moves), {} blocks broken, {:.1} frames/block, {:.1} points/block",
+      self.controller, if self.won() { "won" } else { "lost" }, self.score, self.frames, self.moves,
+      self.blocks_broken(), self.frames_per_block(), self.score_per_block())
+  }
+}
+
+// Plays until the program halts; `max_frames` guards against a controller
+// that never loses nor wins.
+fn run_game(arcade: &mut Arcade, controller: &mut dyn Controller, max_frames: usize) -> GameStats {
+  let mut stats = GameStats {
+    controller: controller.name().to_string(),
+    frames: 0,
+    moves: 0,
+    score: arcade.score,
+    blocks: vec![arcade.blocks()],
+  };
+  while !arcade.finished() && stats.frames < max_frames {
+    let joystick = controller.joystick(arcade);
+    arcade.step(joystick);
+    stats.frames += 1;
+    if joystick != 0 {
+      stats.moves += 1;
     }
-    //print_grid(&grid);
+    stats.blocks.push(arcade.blocks());
   }
-  return (grid,score);
+  stats.score = arcade.score;
+  stats
+}
+
+fn game(state : State) -> (Grid,i64) {
+  let mut arcade = Arcade::new(state);
+  run_game(&mut arcade, &mut FollowBall, usize::MAX);
+  (arcade.grid, arcade.score)
 }
 
 fn q1(filename: impl AsRef<std::path::Path>) -> usize {
-  let mut state = State::new_from_file(filename);
-  let grid = game(&mut state).0;
+  let state = State::new_from_file(filename);
+  let grid = game(state).0;
   return grid.values().filter(|c| **c == 'H').count();
 }
 
 fn q2(filename: impl AsRef<std::path::Path>) -> i64 {
   let mut state = State::new_from_file(filename);
   state.set_mem(0,2);
-  return game(&mut state).1;
+  return game(state).1;
+}
+
+// Where `play` and `replay` start: the save state in `savefile` if there
+// is one, otherwise a new game with free play.
+fn start_arcade(program: impl AsRef<std::path::Path>, savefile: Option<&String>) -> Arcade {
+  match savefile.filter(|p| std::path::Path::new(p).exists()) {
+    Some(path) => Arcade::load(&fs::read_to_string(path).expect("cannot read save state"))
+      .unwrap_or_else(|e| panic!("invalid save state: {}", e)),
+    None => {
+      let mut state = State::new_from_file(program);
+      state.set_mem(0,2);
+      Arcade::new(state)
+    }
+  }
 }
 
+// Writes what a play session leaves: the kept save state to `savefile`,
+// and to `recording` the moves played since the state a later replay will
+// start from. That is the state just written to `savefile` when there is
+// one, otherwise the one the session started in (`first` inputs in). A
+// kept state is always an ancestor of the current one, as 'r' goes back
+// to it, so the moves since it are the tail of `arcade.inputs`.
+fn end_session(arcade: &Arcade, first: usize, slot: Option<String>, recording: Option<&String>, savefile: Option<&String>) -> io::Result<Vec<String>> {
+  let mut messages = Vec::new();
+  let mut base = first;
+  if let (Some(path), Some(saved)) = (savefile, slot) {
+    base = Arcade::load(&saved).expect("slot holds a valid save state").inputs.len();
+    fs::write(path, saved)?;
+    messages.push(format!("Save state written to {}", path));
+  }
+  if let Some(path) = recording {
+    let replay = Replay::record(arcade, base.min(arcade.inputs.len()));
+    fs::write(path, replay.to_string())?;
+    messages.push(format!("{} moves saved to {}", replay.inputs.len(), path));
+  }
+  Ok(messages)
+}
+
+// advent13 play [recording] [savefile]: plays in the terminal (keys a/s/d,
+// k to keep a save state, r to return to it, q to quit) and
+// writes the moves to `recording`. The save state is read from and written
+// back to `savefile`, so a session can be resumed later; the recording
+// then starts from the save state written.
+// advent13 replay <recording> [savefile]: checks a recording still reaches
+// its score, from the start or from the save state.
+// advent13 compare: plays a game with each controller and prints the stats.
 fn main() {
-  println!("Question1: {}", q1("data.txt"));
-  println!("Question2: {}", q2("data.txt"));
+  let args : Vec<String> = std::env::args().collect();
+  match args.get(1).map(|s| s.as_str()) {
+    Some("play") => {
+      let mut arcade = start_arcade("data.txt", args.get(3));
+      let first = arcade.inputs.len();
+      let mut slot = None;
+      let stdin = io::stdin();
+      let stdout = io::stdout();
+      let cbreak = CbreakMode::enter();
+      play(&mut arcade, stdin.lock(), &mut stdout.lock(), true, &mut slot).expect("terminal error");
+      drop(cbreak);
+      println!("{}", if arcade.finished() { "Game over" } else { "Quit" });
+      for message in end_session(&arcade, first, slot, args.get(2), args.get(3)).expect("cannot write session") {
+        println!("{}", message);
+      }
+    }
+    Some("replay") => {
+      let path = args.get(2).expect("usage: advent13 replay <recording> [savefile]");
+      let replay = Replay::parse(&fs::read_to_string(path).expect("cannot read recording"))
+        .unwrap_or_else(|e| panic!("invalid recording: {}", e));
+      let mut arcade = start_arcade("data.txt", args.get(3));
+      match replay.run(&mut arcade) {
+        Ok(()) => println!("Replay reached score {}", arcade.score),
+        Err(e) => println!("Replay failed: {}", e),
+      }
+    }
+    Some("compare") => {
+      let controllers : Vec<Box<dyn Controller>> = vec![Box::new(FollowBall), Box::new(Predictive::default())];
+      for mut controller in controllers {
+        let mut state = State::new_from_file("data.txt");
+        state.set_mem(0,2);
+        println!("{}", run_game(&mut Arcade::new(state), controller.as_mut(), 1_000_000));
+      }
+    }
+    _ => {
+      println!("Question1: {}", q1("data.txt"));
+      println!("Question2: {}", q2("data.txt"));
+    }
+  }
+}
+
+#[test]
+fn test_answers() {
+  assert_eq!(q1("data.txt"), 268);
+  assert_eq!(q2("data.txt"), 13989);
+}
+
+#[test]
+fn test_headless_play() {
+  let mut state = State::new_from_file("data.txt");
+  state.set_mem(0,2);
+  let mut arcade = Arcade::new(state);
+  let screen = arcade.render();
+  assert!(screen.starts_with("Score: 0\n"));
+  let board = render_grid(&arcade.grid);
+  assert!(screen.ends_with(&board));
+  assert_eq!(board.matches('o').count(), 1);
+  assert_eq!(board.matches('_').count(), 1);
+  assert_eq!(arcade.blocks(), 268);
+  let mut out = Vec::new();
+  play(&mut arcade, "ad s\n>x<q.".as_bytes(), &mut out, false, &mut None).unwrap();
+  assert!(out.is_empty());
+  assert_eq!(arcade.inputs, vec![-1, 1, 0, 1, -1]);
+  assert_eq!(parse_values(&recording_to_string(&arcade.inputs)), Ok(arcade.inputs.clone()));
+  let mut out = Vec::new();
+  play(&mut arcade, "s".as_bytes(), &mut out, true, &mut None).unwrap();
+  assert_eq!(String::from_utf8(out).unwrap().matches("Score: ").count(), 2);
+}
+
+#[test]
+fn test_save_and_replay() {
+  let mut state = State::new_from_file("data.txt");
+  state.set_mem(0,2);
+  let mut arcade = Arcade::new(state);
+  for _ in 0..500 {
+    arcade.step((arcade.pos_ball.0 - arcade.pos_pad.0).signum() as i64);
+  }
+  let saved = arcade.save();
+  let loaded = Arcade::load(&saved).unwrap();
+  assert_eq!(loaded.save(), saved);
+  assert_eq!(loaded.render(), arcade.render());
+  assert_eq!((loaded.pos_ball, loaded.pos_pad, loaded.inputs.len()), (arcade.pos_ball, arcade.pos_pad, 500));
+
+  // Keep, lose the ball, then go back and finish with the auto-player.
+  let mut slot = None;
+  play(&mut arcade, "k".as_bytes(), &mut Vec::new(), false, &mut slot).unwrap();
+  assert_eq!(slot.as_deref(), Some(saved.as_str()));
+  while !arcade.finished() {
+    arcade.step(1);
+  }
+  assert!(arcade.blocks() > 0);
+  play(&mut arcade, "r".as_bytes(), &mut Vec::new(), false, &mut slot).unwrap();
+  assert!(!arcade.finished());
+  while !arcade.finished() {
+    arcade.step((arcade.pos_ball.0 - arcade.pos_pad.0).signum() as i64);
+  }
+  assert_eq!((arcade.score, arcade.blocks()), (13989, 0));
+
+  // The whole run replays from scratch, and the tail replays from the save.
+  let replay = Replay::parse(&Replay::record(&arcade, 0).to_string()).unwrap();
+  let mut state = State::new_from_file("data.txt");
+  state.set_mem(0,2);
+  let mut fresh = Arcade::new(state);
+  assert_eq!(replay.run(&mut fresh), Ok(()));
+  let tail = Replay::record(&arcade, 500);
+  assert_eq!(tail.run(&mut Arcade::load(&saved).unwrap()), Ok(()));
+  let wrong = Replay { inputs: tail.inputs.clone(), score: 1 };
+  assert_eq!(wrong.run(&mut Arcade::load(&saved).unwrap()), Err("replay reached score 13989 instead of 1".to_string()));
+  let long = Replay { inputs: vec![0; tail.inputs.len() + 1], score: 0 };
+  assert!(long.run(&mut Arcade::load(&saved).unwrap()).unwrap_err().starts_with("game over after"));
+  assert_eq!(Arcade::load("ip 0").err(), Some("missing field mem".to_string()));
+}
+
+#[test]
+fn test_controllers() {
+  struct Still;
+  impl Controller for Still {
+    fn name(&self) -> &str { "still" }
+    fn joystick(&mut self, _: &Arcade) -> i64 { 0 }
+  }
+  let start = || {
+    let mut state = State::new_from_file("data.txt");
+    state.set_mem(0,2);
+    Arcade::new(state)
+  };
+  let follow = run_game(&mut start(), &mut FollowBall, 1_000_000);
+  let predictive = run_game(&mut start(), &mut Predictive::default(), 1_000_000);
+  for stats in [&follow, &predictive] {
+    assert!(stats.won());
+    assert_eq!((stats.score, stats.frames, stats.blocks_broken()), (13989, 5013, 268));
+    assert_eq!(stats.blocks.len(), stats.frames + 1);
+    assert!(stats.blocks.windows(2).all(|w| w[1] <= w[0]));
+  }
+  assert!(predictive.moves < follow.moves);
+  let still = run_game(&mut start(), &mut Still, 1_000_000);
+  assert!(!still.won());
+  assert_eq!(still.moves, 0);
+  assert!(format!("{}", still).starts_with("still: lost score"));
+  assert_eq!(run_game(&mut start(), &mut FollowBall, 10).frames, 10);
+}
+
+#[test]
+fn test_keypresses_and_wrapped_output() {
+  let mut state = State::new_from_file("data.txt");
+  state.set_mem(0,2);
+  let mut arcade = Arcade::new(state);
+  // Keys act as soon as they are read: no line structure is needed, and
+  // newlines or carriage returns are just ignored bytes.
+  play(&mut arcade, &b"a\r\nd\n\ns"[..], &mut Vec::new(), false, &mut None).unwrap();
+  assert_eq!(arcade.inputs, vec![-1, 1, 0]);
+  play(&mut arcade, &b"dq"[..], &mut Vec::new(), false, &mut None).unwrap();
+  assert_eq!(arcade.inputs, vec![-1, 1, 0, 1]);
+
+  // A triplet split by the ring buffer wrapping still draws as one tile.
+  let mut output : VecDeque<i64> = VecDeque::with_capacity(6);
+  for v in [0, 0, 0, 0, 5, 7] {
+    output.push_back(v);
+  }
+  for _ in 0..4 {
+    output.pop_front();
+  }
+  for v in [2, -1, 0, 42] {
+    output.push_back(v);
+  }
+  assert!(!output.as_slices().1.is_empty());
+  arcade.draw_all(output);
+  assert_eq!(arcade.grid.get(&(5, 7)), Some(&'H'));
+  assert_eq!(arcade.score, 42);
+}
+
+#[test]
+fn test_play_session_files() {
+  let dir = std::env::temp_dir().join(format!("advent13_session_{}", std::process::id()));
+  fs::create_dir_all(&dir).unwrap();
+  let rec = dir.join("rec.txt").to_string_lossy().into_owned();
+  let save = dir.join("save.txt").to_string_lossy().into_owned();
+  let replay_score = |savefile: Option<&String>| {
+    let replay = Replay::parse(&fs::read_to_string(&rec).unwrap()).unwrap();
+    let mut arcade = start_arcade("data.txt", savefile);
+    replay.run(&mut arcade).map(|_| arcade.score)
+  };
+
+  // Keep a state mid-session: the recording starts from it.
+  let mut arcade = start_arcade("data.txt", Some(&save));
+  let first = arcade.inputs.len();
+  let mut slot = None;
+  play(&mut arcade, &b"dddk"[..], &mut Vec::new(), false, &mut slot).unwrap();
+  let messages = end_session(&arcade, first, slot, Some(&rec), Some(&save)).unwrap();
+  assert_eq!(messages[1], format!("0 moves saved to {}", rec));
+  assert_eq!(replay_score(Some(&save)), Ok(0));
+
+  // Follow the ball for a while, keep, go on, and write both files.
+  let follow = |arcade: &mut Arcade, frames: usize| {
+    let keys : String = (0..frames).map(|_| {
+      let key = ['a', 's', 'd'][((arcade.pos_ball.0 - arcade.pos_pad.0).signum() + 1) as usize];
+      play(arcade, key.to_string().as_bytes(), &mut Vec::new(), false, &mut None).unwrap();
+      key
+    }).collect();
+    keys
+  };
+  fs::remove_file(&save).unwrap();
+  let mut arcade = start_arcade("data.txt", Some(&save));
+  let mut slot = None;
+  follow(&mut arcade, 50);
+  play(&mut arcade, &b"k"[..], &mut Vec::new(), false, &mut slot).unwrap();
+  end_session(&arcade, 0, slot, Some(&rec), Some(&save)).unwrap();
+
+  // Resume from it, keep again later, and go on past the kept state.
+  let mut arcade = start_arcade("data.txt", Some(&save));
+  assert_eq!(arcade.inputs.len(), 50);
+  let first = arcade.inputs.len();
+  let mut slot = None;
+  follow(&mut arcade, 20);
+  play(&mut arcade, &b"k"[..], &mut Vec::new(), false, &mut slot).unwrap();
+  let kept = slot.clone().unwrap();
+  let keys = follow(&mut arcade, 30);
+  end_session(&arcade, first, slot, Some(&rec), Some(&save)).unwrap();
+  assert!(!arcade.finished());
+  assert_eq!(fs::read_to_string(&save).unwrap(), kept);
+  let expected : Vec<i64> = keys.chars().map(|k| key_to_joystick(k).unwrap()).collect();
+  assert_eq!(Replay::parse(&fs::read_to_string(&rec).unwrap()).unwrap().inputs, expected);
+  assert_eq!(replay_score(Some(&save)), Ok(arcade.score));
+
+  // Without a save file the recording covers the whole session.
+  let mut arcade = start_arcade("data.txt", None);
+  let mut slot = None;
+  play(&mut arcade, &b"sksd"[..], &mut Vec::new(), false, &mut slot).unwrap();
+  end_session(&arcade, 0, slot, Some(&rec), None).unwrap();
+  assert_eq!(Replay::parse(&fs::read_to_string(&rec).unwrap()).unwrap().inputs.len(), 3);
+  assert_eq!(replay_score(None), Ok(arcade.score));
+  fs::remove_dir_all(&dir).unwrap();
 }
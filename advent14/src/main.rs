@@ -1,109 +1,454 @@
 use std::fs;
-//use std::collections::HashSet;
+use std::collections::HashSet;
 use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt;
 //use std::collections::VecDeque;
 
-fn parse_num_token(num_token: &str) -> (i32,String) {
-  let mut part = num_token.split(" ");
-  let num = part.next().expect("a text should be present")
-              .parse::<i32>().expect("a number is expected");
-  let txt = part.next().expect("a token should be present").to_string();
-  return (num,txt);
+// A quantity of a chemical, as in `7 ORE`.
+type Term = (u64, String);
+
+fn parse_num_token(num_token: &str) -> Result<Term, String> {
+  let mut part = num_token.trim().split(' ').filter(|p| !p.is_empty());
+  let num = part.next().ok_or("missing quantity")?;
+  let num = num.parse::<u64>().map_err(|e| format!("quantity {:?}: {}", num, e))?;
+  let txt = part.next().ok_or(format!("missing chemical after {}", num))?;
+  if part.next().is_some() || !txt.chars().all(|c| c.is_ascii_alphanumeric()) {
+    return Err(format!("expected `quantity CHEMICAL`, found {:?}", num_token.trim()));
+  }
+  if num == 0 {
+    return Err(format!("quantity of {} must be positive", txt));
+  }
+  Ok((num, txt.to_string()))
+}
+
+fn parse_line(line: &str) -> Result<(Vec<Term>,Term), String> {
+  let (left_str, right_str) = line.split_once("=>").ok_or("missing =>")?;
+  let left = left_str.split(',').map(parse_num_token).collect::<Result<Vec<_>, String>>()?;
+  let right = parse_num_token(right_str)?;
+  Ok((left,right))
 }
 
-fn parse_line(line: &str) -> (Vec<(i32,String)>,(i32,String)) {
-  let mut part = line.split(" => ");
-  let left_str = part.next().expect("a left part should be present").to_string();
-  let right_str = part.next().expect("a right part should be present").to_string();
-  let left = left_str.split(", ").map(|s| parse_num_token(&s)).collect();
-  let right = parse_num_token(&right_str);
-  return (left,right);
+type Formulas = HashMap<String, (u64, Vec<Term>)>;
+
+// One reaction with the input line it comes from (from 1).
+struct Reaction {
+  line: usize,
+  output: Term,
+  inputs: Vec<Term>,
 }
 
-type Formulas = HashMap<String, (i32, Vec<(i32,String)>)>;
+#[derive(Debug, Clone, PartialEq)]
+enum RecipeIssue {
+  Malformed { line: usize, message: String },
+  DuplicateProducer { chemical: String, lines: Vec<usize> },
+  UndefinedInput { chemical: String, lines: Vec<usize> },
+  Cycle { chemicals: Vec<String>, lines: Vec<usize> },
+  Unreachable { chemical: String, line: usize },
+  UnusedReaction { chemical: String, line: usize },
+}
+
+impl RecipeIssue {
+  // Unreachable chemicals and unused reactions do not stop the target from
+  // being solved; everything else does.
+  fn is_error(&self) -> bool {
+    !matches!(self, RecipeIssue::Unreachable { .. } | RecipeIssue::UnusedReaction { .. })
+  }
+}
+
+fn join_lines(lines: &[usize]) -> String {
+  lines.iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+impl fmt::Display for RecipeIssue {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      RecipeIssue::Malformed { line, message } => write!(f, "line {}: {}", line, message),
+      RecipeIssue::DuplicateProducer { chemical, lines } => write!(f, "{} is produced by several reactions (lines {})", chemical, join_lines(lines)),
+      RecipeIssue::UndefinedInput { chemical, lines } => write!(f, "{} is used but never produced (lines {})", chemical, join_lines(lines)),
+      RecipeIssue::Cycle { chemicals, lines } => write!(f, "reaction cycle {} (lines {})", chemicals.join(" -> "), join_lines(lines)),
+      RecipeIssue::Unreachable { chemical, line } => write!(f, "line {}: {} cannot be made from the source", line, chemical),
+      RecipeIssue::UnusedReaction { chemical, line } => write!(f, "line {}: {} is not needed for the target", line, chemical),
+    }
+  }
+}
+
+fn parse_reactions(data: &str) -> (Vec<Reaction>, Vec<RecipeIssue>) {
+  let mut reactions = Vec::new();
+  let mut issues = Vec::new();
+  for (i, line) in data.lines().enumerate() {
+    if line.trim().is_empty() {
+      continue;
+    }
+    match parse_line(line) {
+      Ok((inputs, output)) => reactions.push(Reaction { line: i + 1, output, inputs }),
+      Err(message) => issues.push(RecipeIssue::Malformed { line: i + 1, message }),
+    }
+  }
+  (reactions, issues)
+}
+
+// Checks the reactions make a clean recipe for `target` out of `source`:
+// one producer per chemical, every input produced (or the source), no
+// cycles, every product reachable from the source through reactions whose
+// inputs are all available, and every reaction needed by the target.
+fn validate(reactions: &[Reaction], target: &str, source: &str) -> Vec<RecipeIssue> {
+  let mut issues = Vec::new();
+  let mut producers : BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+  for r in reactions {
+    producers.entry(r.output.1.as_str()).or_default().push(r.line);
+  }
+  for (chemical, lines) in &producers {
+    if lines.len() > 1 {
+      issues.push(RecipeIssue::DuplicateProducer { chemical: chemical.to_string(), lines: lines.clone() });
+    }
+  }
+  let mut undefined : BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+  for r in reactions {
+    for (_, input) in &r.inputs {
+      if input != source && !producers.contains_key(input.as_str()) {
+        undefined.entry(input.as_str()).or_default().push(r.line);
+      }
+    }
+  }
+  for (chemical, lines) in undefined {
+    issues.push(RecipeIssue::UndefinedInput { chemical: chemical.to_string(), lines });
+  }
+
+  // Cycles, by depth first search. A chemical depends on the inputs of all
+  // its producers; edges keep the line of the reaction they come from.
+  let mut edges : HashMap<&str, Vec<(&str, usize)>> = HashMap::new();
+  for r in reactions {
+    edges.entry(r.output.1.as_str()).or_default().extend(r.inputs.iter().map(|(_, i)| (i.as_str(), r.line)));
+  }
+  let mut state : HashMap<&str, bool> = HashMap::new(); // false: on the stack, true: done
+  for r in reactions {
+    let start = r.output.1.as_str();
+    if state.contains_key(start) {
+      continue;
+    }
+    // (chemical, index of the next edge, line of the edge that led here)
+    let mut stack : Vec<(&str, usize, usize)> = vec![(start, 0, 0)];
+    state.insert(start, false);
+    while let Some((chemical, next, via)) = stack.pop() {
+      let out = edges.get(chemical).map_or(&[][..], |e| &e[..]);
+      if next == out.len() {
+        state.insert(chemical, true);
+        continue;
+      }
+      stack.push((chemical, next + 1, via));
+      let (input, line) = out[next];
+      match state.get(input) {
+        None if edges.contains_key(input) => {
+          state.insert(input, false);
+          stack.push((input, 0, line));
+        }
+        Some(false) => {
+          let first = stack.iter().position(|(c, _, _)| *c == input).expect("on the stack");
+          let mut chemicals : Vec<String> = stack[first..].iter().map(|(c, _, _)| c.to_string()).collect();
+          let mut lines : Vec<usize> = stack[first + 1..].iter().map(|(_, _, l)| *l).collect();
+          chemicals.push(input.to_string());
+          lines.push(line);
+          issues.push(RecipeIssue::Cycle { chemicals, lines });
+        }
+        _ => {}
+      }
+    }
+  }
+
+  // Reachable: made by a reaction whose inputs are all reachable.
+  let mut reachable : HashSet<&str> = HashSet::new();
+  reachable.insert(source);
+  loop {
+    let before = reachable.len();
+    for r in reactions {
+      if r.inputs.iter().all(|(_, i)| reachable.contains(i.as_str())) {
+        reachable.insert(r.output.1.as_str());
+      }
+    }
+    if reachable.len() == before {
+      break;
+    }
+  }
+  // Needed: the target and, transitively, the inputs of their reactions.
+  let mut needed : HashSet<&str> = HashSet::new();
+  let mut todo = vec![target];
+  while let Some(chemical) = todo.pop() {
+    if needed.insert(chemical) {
+      for r in reactions.iter().filter(|r| r.output.1 == chemical) {
+        todo.extend(r.inputs.iter().map(|(_, i)| i.as_str()));
+      }
+    }
+  }
+  for r in reactions {
+    if !reachable.contains(r.output.1.as_str()) {
+      issues.push(RecipeIssue::Unreachable { chemical: r.output.1.clone(), line: r.line });
+    }
+    if !needed.contains(r.output.1.as_str()) {
+      issues.push(RecipeIssue::UnusedReaction { chemical: r.output.1.clone(), line: r.line });
+    }
+  }
+  issues
+}
+
+// Parses and validates a reaction list; warnings alone do not make it fail.
+fn load(data: &str, target: &str, source: &str) -> Result<Formulas, Vec<RecipeIssue>> {
+  let (reactions, mut issues) = parse_reactions(data);
+  issues.extend(validate(&reactions, target, source));
+  if issues.iter().any(|i| i.is_error()) {
+    return Err(issues);
+  }
+  Ok(reactions.into_iter().map(|r| (r.output.1, (r.output.0, r.inputs))).collect())
+}
 
 fn parse(filename: impl AsRef<std::path::Path>) -> Formulas {
   let data = fs::read_to_string(filename).expect("Something went wrong reading the file");
-  let mut dico = HashMap::new();
-  for line in data.lines()
-  {
-    let tmp = parse_line(line);
-    dico.insert((tmp.1).1,((tmp.1).0,tmp.0));
+  load(&data, "FUEL", "ORE").unwrap_or_else(|issues| {
+    let issues : Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+    panic!("invalid reactions:\n{}", issues.join("\n"))
+  })
+}
+
+// Length of the longest chain of reactions from `source` to each chemical
+// (the source itself is at 0). The formulas must be acyclic.
+fn depths(formulas : &Formulas, source: &str) -> BTreeMap<String, usize> {
+  fn depth(formulas : &Formulas, what: &str, source: &str, memo: &mut BTreeMap<String, usize>) -> usize {
+    if let Some(d) = memo.get(what) {
+      return *d;
+    }
+    let d = match formulas.get(what) {
+      Some((_, inputs)) if what != source => 1 + inputs.iter().map(|(_, i)| depth(formulas, i, source, memo)).max().unwrap_or(0),
+      _ => 0,
+    };
+    memo.insert(what.to_string(), d);
+    d
+  }
+  let mut memo = BTreeMap::new();
+  memo.insert(source.to_string(), 0);
+  for what in formulas.keys() {
+    depth(formulas, what, source, &mut memo);
+  }
+  memo
+}
+
+// Graphviz graph with an edge from each input to its product, labelled
+// with the quantity used, and the chemicals ranked by depth from `source`.
+fn to_dot(formulas : &Formulas, source: &str) -> String {
+  let depths = depths(formulas, source);
+  let mut out = String::from("digraph reactions {\n  rankdir=BT;\n");
+  let max_depth = depths.values().copied().max().unwrap_or(0);
+  for d in 0..=max_depth {
+    let names : Vec<String> = depths.iter().filter(|(_, v)| **v == d).map(|(k, _)| format!("\"{}\"", k)).collect();
+    out += &format!("  {{ rank=same; {}; }}\n", names.join("; "));
+  }
+  for (what, d) in &depths {
+    let made = formulas.get(what).filter(|_| what != source).map_or(String::new(), |(n, _)| format!(" x{}", n));
+    out += &format!("  \"{}\" [label=\"{}{}\\ndepth {}\"];\n", what, what, made, d);
+  }
+  let mut outputs : Vec<&String> = formulas.keys().collect();
+  outputs.sort();
+  for what in outputs {
+    for (qt, input) in &formulas[what].1 {
+      out += &format!("  \"{}\" -> \"{}\" [label=\"{}\", weight={}];\n", input, what, qt, qt);
+    }
+  }
+  out += "}\n";
+  out
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SolveError {
+  Cycle(Vec<String>), // the chemicals along the cycle, first one repeated at the end
+  NoReaction(String), // neither produced by a reaction nor the source
+  Overflow(String),   // a quantity of this chemical does not fit in a u128
+}
+
+impl fmt::Display for SolveError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SolveError::Cycle(path) => write!(f, "reactions form a cycle: {}", path.join(" -> ")),
+      SolveError::NoReaction(what) => write!(f, "no reaction produces {}", what),
+      SolveError::Overflow(what) => write!(f, "amount of {} overflows", what),
+    }
   }
-  return dico;
 }
 
-fn create(formulas : &Formulas, what: &String, qt_needed : i32, mut available : &mut HashMap<String,i32>, mut ore_cost : &mut i64) -> ()
-{
-  if what == "ORE"
-  {
-    *ore_cost = *ore_cost + qt_needed as i64;
-    return;
+// Chemicals needed to make `target`, each one listed before everything it
+// is made from, ending with `source`. Only what `target` depends on is
+// visited.
+fn topological_order(formulas : &Formulas, target: &str, source: &str) -> Result<Vec<String>, SolveError> {
+  fn visit(formulas : &Formulas, what: &str, source: &str, path: &mut Vec<String>,
+           done: &mut HashSet<String>, order: &mut Vec<String>) -> Result<(), SolveError> {
+    if done.contains(what) {
+      return Ok(());
+    }
+    if let Some(start) = path.iter().position(|p| p == what) {
+      let mut cycle = path[start..].to_vec();
+      cycle.push(what.to_string());
+      return Err(SolveError::Cycle(cycle));
+    }
+    if what != source {
+      let (_, inputs) = formulas.get(what).ok_or(SolveError::NoReaction(what.to_string()))?;
+      path.push(what.to_string());
+      for (_, input) in inputs {
+        visit(formulas, input, source, path, done, order)?;
+      }
+      path.pop();
+    }
+    done.insert(what.to_string());
+    order.push(what.to_string());
+    Ok(())
   }
+  let mut order = Vec::new();
+  visit(formulas, target, source, &mut Vec::new(), &mut HashSet::new(), &mut order)?;
+  order.reverse();
+  Ok(order)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Flow {
+  produced: u128, // made by reactions (for the source: taken from the stock)
+  consumed: u128, // used by reactions, plus the requested amount for the target
+  leftover: u128,
+}
 
-  let mut qt_available = *(available.get(what).unwrap_or(&0));
-  qt_available = qt_available - qt_needed;
-  available.insert(what.to_string(), qt_available);
-  if qt_available < 0
-  {
-    let qt_list = formulas.get(what).expect("unknow formula bit");
-    let list = &qt_list.1;
-    let qt_by_reaction = qt_list.0;
-    let n = (- qt_available - 1) / qt_by_reaction + 1;
-    for elem in list
-    {
-      create(formulas, &elem.1, n * elem.0, &mut available, &mut ore_cost);
+// Everything that goes on to make `amount` of `target` from `source`.
+#[derive(Debug, Clone, PartialEq)]
+struct Breakdown {
+  source: String,
+  target: String,
+  amount: u64,
+  flows: BTreeMap<String, Flow>,
+}
+
+impl Breakdown {
+  fn source_needed(&self) -> u128 {
+    self.flows.get(&self.source).map_or(0, |f| f.produced)
+  }
+}
+
+impl fmt::Display for Breakdown {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "{} {} from {} {}", self.amount, self.target, self.source_needed(), self.source)?;
+    writeln!(f, "{:>8} {:>16} {:>16} {:>16}", "chemical", "produced", "consumed", "leftover")?;
+    for (what, flow) in &self.flows {
+      writeln!(f, "{:>8} {:>16} {:>16} {:>16}", what, flow.produced, flow.consumed, flow.leftover)?;
     }
-    qt_available = qt_available + n * qt_by_reaction;
-    available.insert(what.to_string(), qt_available);
+    Ok(())
   }
 }
 
-fn q1(filename: impl AsRef<std::path::Path>) -> i32 {
+// Needs only grow while walking down the topological order, so each
+// chemical is reacted once, for all its consumers together: that gives the
+// fewest batches and the least waste.
+fn solve(formulas : &Formulas, target: &str, amount: u64, source: &str) -> Result<Breakdown, SolveError> {
+  let order = topological_order(formulas, target, source)?;
+  let mut needed : HashMap<&str, u128> = HashMap::new();
+  needed.insert(target, amount as u128);
+  let mut flows = BTreeMap::new();
+  for what in &order {
+    let need = needed.get(what.as_str()).copied().unwrap_or(0);
+    if what == source {
+      flows.insert(what.clone(), Flow { produced: need, consumed: need, leftover: 0 });
+      continue;
+    }
+    let (per_batch, inputs) = &formulas[what];
+    let batches = need.div_ceil(*per_batch as u128);
+    for (qt, input) in inputs {
+      let total = needed.entry(input.as_str()).or_insert(0);
+      *total = batches.checked_mul(*qt as u128).and_then(|n| n.checked_add(*total))
+        .ok_or_else(|| SolveError::Overflow(input.clone()))?;
+    }
+    let produced = batches.checked_mul(*per_batch as u128).ok_or_else(|| SolveError::Overflow(what.clone()))?;
+    flows.insert(what.clone(), Flow { produced, consumed: need, leftover: produced - need });
+  }
+  Ok(Breakdown { source: source.to_string(), target: target.to_string(), amount, flows })
+}
+
+fn q1(filename: impl AsRef<std::path::Path>) -> u128 {
   let formulas = parse(filename);
-  let mut available = HashMap::new();
-  let mut ore_cost = 0;
-  create(&formulas, &("FUEL".to_string()), 1, &mut available, &mut ore_cost);
-  //println!("available {:?}", available);
-  return ore_cost as i32;
-}
-
-fn fuel_batch_max_create(formulas : &Formulas, batch: i32, mut available : &mut HashMap<String,i32>, mut ore_cost : &mut i64) -> (i32, HashMap<String,i32>)
-{
-  let mut nb_created_before = 0;
-  let mut ore_cost_before : i64 = 0;
-  let mut available_before = available.clone();
-  let mut nb_created = 0;
-  while *ore_cost < 1000000000000
-  {
-    available_before = available.clone();
-    nb_created_before = nb_created;
-    ore_cost_before = *ore_cost;
-    create(&formulas, &("FUEL".to_string()), batch, &mut available, &mut ore_cost);
-    nb_created = nb_created + batch;
-    println!("cost: {}, fuel: {} ", ore_cost, nb_created);
-  }
-  *ore_cost = ore_cost_before;
-  return (nb_created_before, available_before);
-}
-
-fn q2(filename: impl AsRef<std::path::Path>) -> i32 {
+  solve(&formulas, "FUEL", 1, "ORE").unwrap_or_else(|e| panic!("{}", e)).source_needed()
+}
+
+// Least amount of `source` that makes `amount` of `target`.
+fn min_source(formulas : &Formulas, target: &str, amount: u64, source: &str) -> Result<u128, SolveError> {
+  Ok(solve(formulas, target, amount, source)?.source_needed())
+}
+
+// Most `target` that `budget` units of `source` can make. The cost grows
+// with the amount, so the answer is found by doubling an upper bound and
+// then bisecting, each probe being one pass of the solver.
+fn max_target(formulas : &Formulas, target: &str, source: &str, budget: u128) -> Result<u64, SolveError> {
+  // A cost too large for a u128 is certainly over the budget.
+  let fits = |amount: u64| match min_source(formulas, target, amount, source) {
+    Ok(cost) => Ok(cost <= budget),
+    Err(SolveError::Overflow(_)) => Ok(false),
+    Err(e) => Err(e),
+  };
+  if !fits(1)? {
+    return Ok(0);
+  }
+  let mut lo = 1u64; // always fits
+  let mut hi = 2u64; // may not fit
+  while fits(hi)? {
+    lo = hi;
+    if hi == u64::MAX {
+      return Ok(hi);
+    }
+    hi = hi.saturating_mul(2);
+  }
+  while hi - lo > 1 {
+    let mid = lo + (hi - lo) / 2;
+    if fits(mid)? { lo = mid; } else { hi = mid; }
+  }
+  Ok(lo)
+}
+
+const ORE_BUDGET : u128 = 1_000_000_000_000;
+
+fn q2(filename: impl AsRef<std::path::Path>) -> u64 {
   let formulas = parse(filename);
-  let mut ore_cost = 0;
-  let mut nb_created = 0;
-  let mut ret = (0,HashMap::new());
-  for batch in [1000,100,10,1].iter()
-  {
-    ret = fuel_batch_max_create(&formulas,*batch as i32,&mut ret.1, &mut ore_cost);
-    nb_created = nb_created + ret.0;
-    println!("total fuel: {} ", nb_created);    
-  }  
-  return nb_created;
+  max_target(&formulas, "FUEL", "ORE", ORE_BUDGET).unwrap_or_else(|e| panic!("{}", e))
 }
 
+// advent14 [fuel]: also prints what making `fuel` FUEL takes.
+// advent14 check [file]: lists problems in a reaction file and the depths.
+// advent14 dot <output> [file]: writes the reaction graph for Graphviz.
+// advent14 <budget> <source> <target>: how much `target` the budget makes.
 fn main() {
   println!("{:?}", q1("data.txt"));
   println!("{:?}", q2("data.txt"));
+  let args : Vec<String> = std::env::args().collect();
+  if args.get(1).map(|s| s.as_str()) == Some("check") {
+    let file = args.get(2).map_or("data.txt", |s| s.as_str());
+    let data = fs::read_to_string(file).expect("Something went wrong reading the file");
+    let (reactions, mut issues) = parse_reactions(&data);
+    issues.extend(validate(&reactions, "FUEL", "ORE"));
+    for issue in &issues {
+      println!("{}: {}", if issue.is_error() { "error" } else { "warning" }, issue);
+    }
+    if let Ok(formulas) = load(&data, "FUEL", "ORE") {
+      for (what, d) in depths(&formulas, "ORE") {
+        println!("{} {}", d, what);
+      }
+    }
+  } else if args.get(1).map(|s| s.as_str()) == Some("dot") {
+    let out = args.get(2).expect("usage: advent14 dot <output> [file]");
+    let formulas = parse(args.get(3).map_or("data.txt", |s| s.as_str()));
+    fs::write(out, to_dot(&formulas, "ORE")).expect("cannot write dot file");
+  } else if let [_, budget, source, target] = &args[..] {
+    let budget = budget.parse::<u128>().expect("budget must be a number");
+    match max_target(&parse("data.txt"), target, source, budget) {
+      Ok(amount) => println!("{} {} makes {} {}", budget, source, amount, target),
+      Err(e) => println!("{}", e),
+    }
+  } else if let Some(fuel) = args.get(1) {
+    let fuel = fuel.parse::<u64>().expect("fuel must be a number");
+    match solve(&parse("data.txt"), "FUEL", fuel, "ORE") {
+      Ok(breakdown) => print!("{}", breakdown),
+      Err(e) => println!("{}", e),
+    }
+  }
 }
 
 #[test]
@@ -139,4 +484,126 @@ fn test_q2_examples2() {
 #[test]
 fn test_q2_examples3() {
   assert_eq!(q2("test3.txt"), 460664);
-}
\ No newline at end of file
+}
+#[test]
+fn test_solve() {
+  let formulas = parse("test4.txt");
+  let breakdown = solve(&formulas, "FUEL", 1, "ORE").unwrap();
+  assert_eq!(breakdown.source_needed(), 165);
+  assert_eq!(breakdown.flows["A"], Flow { produced: 10, consumed: 10, leftover: 0 });
+  assert_eq!(breakdown.flows["B"], Flow { produced: 24, consumed: 23, leftover: 1 });
+  assert_eq!(breakdown.flows["C"], Flow { produced: 40, consumed: 37, leftover: 3 });
+  assert_eq!(breakdown.flows["FUEL"], Flow { produced: 1, consumed: 1, leftover: 0 });
+  assert_eq!(breakdown.flows["ORE"], Flow { produced: 165, consumed: 165, leftover: 0 });
+  // Big amounts go past i32 and u64 ore costs without trouble.
+  let big = solve(&parse("data.txt"), "FUEL", u64::MAX, "ORE").unwrap();
+  assert!(big.source_needed() > u64::MAX as u128);
+  // Asking for an intermediate chemical only walks its own sub-graph.
+  assert_eq!(solve(&formulas, "AB", 2, "ORE").unwrap().flows.keys().collect::<Vec<_>>(), vec!["A", "AB", "B", "ORE"]);
+  assert_eq!(solve(&formulas, "AB", 1, "B").err(), Some(SolveError::NoReaction("ORE".to_string())));
+  let mut cyclic = formulas.clone();
+  cyclic.insert("A".to_string(), (2, vec![(1, "CA".to_string())]));
+  assert_eq!(solve(&cyclic, "FUEL", 1, "ORE").err().map(|e| e.to_string()),
+             Some("reactions form a cycle: A -> CA -> A".to_string()));
+}
+
+#[test]
+fn test_max_target() {
+  let formulas = parse("test1.txt");
+  let fuel = max_target(&formulas, "FUEL", "ORE", ORE_BUDGET).unwrap();
+  assert!(min_source(&formulas, "FUEL", fuel, "ORE").unwrap() <= ORE_BUDGET);
+  assert!(min_source(&formulas, "FUEL", fuel + 1, "ORE").unwrap() > ORE_BUDGET);
+  assert_eq!(max_target(&formulas, "FUEL", "ORE", 13311).unwrap(), 0);
+  assert_eq!(max_target(&formulas, "FUEL", "ORE", 13312).unwrap(), 1);
+  let formulas = parse("test4.txt");
+  // 1 AB takes 3 A and 4 B: 2 batches each, 18 + 16 ORE.
+  assert_eq!(min_source(&formulas, "AB", 1, "ORE").unwrap(), 34);
+  assert_eq!(max_target(&formulas, "AB", "ORE", 34).unwrap(), 1);
+  // Any chemical can be the budget, as long as it is all the target needs.
+  assert_eq!(min_source(&formulas, "A", 5, "ORE").unwrap(), 27);
+  assert_eq!(max_target(&formulas, "A", "ORE", 27).unwrap(), 6);
+  assert_eq!(max_target(&formulas, "FUEL", "A", 1000).err(), Some(SolveError::NoReaction("ORE".to_string())));
+  assert_eq!(max_target(&formulas, "A", "ORE", u128::MAX).unwrap(), u64::MAX);
+}
+
+#[test]
+fn test_validate() {
+  let data = "9 ORE => 2 A\n\
+              1 A, 2 X => 1 B\n\
+              oops\n\
+              3 A => 1 FUEL\n\
+              1 ORE => 1 A\n\
+              1 D => 1 C\n\
+              1 C => 1 D\n\
+              1 A => 1 E\n\
+              1 A => 0x FUEL";
+  let (reactions, issues) = parse_reactions(data);
+  assert_eq!(reactions.len(), 7);
+  assert_eq!(issues.iter().map(|i| i.to_string()).collect::<Vec<_>>(), vec![
+    "line 3: missing =>",
+    "line 9: quantity \"0x\": invalid digit found in string",
+  ]);
+  let issues : Vec<String> = validate(&reactions, "FUEL", "ORE").iter().map(|i| i.to_string()).collect();
+  assert_eq!(issues, vec![
+    "A is produced by several reactions (lines 1, 5)",
+    "X is used but never produced (lines 2)",
+    "reaction cycle C -> D -> C (lines 6, 7)",
+    "line 2: B cannot be made from the source",
+    "line 2: B is not needed for the target",
+    "line 6: C cannot be made from the source",
+    "line 6: C is not needed for the target",
+    "line 7: D cannot be made from the source",
+    "line 7: D is not needed for the target",
+    "line 8: E is not needed for the target",
+  ]);
+  let warnings = load("1 ORE => 1 A\n1 A => 1 FUEL\n1 A => 1 E", "FUEL", "ORE").unwrap();
+  assert_eq!(warnings.len(), 3);
+  assert_eq!(load("1 ORE => 1 A\n1 A => 1 A", "A", "ORE").err().map(|i| i.len()), Some(2));
+  for file in ["test1.txt", "test2.txt", "test3.txt", "test4.txt", "data.txt"].iter() {
+    let data = fs::read_to_string(file).unwrap();
+    let (reactions, issues) = parse_reactions(&data);
+    assert!(issues.is_empty() && validate(&reactions, "FUEL", "ORE").is_empty(), "{}", file);
+  }
+}
+
+#[test]
+fn test_dot() {
+  let formulas = parse("test4.txt");
+  let d = depths(&formulas, "ORE");
+  assert_eq!(d.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>(),
+             vec!["A=1", "AB=2", "B=1", "BC=2", "C=1", "CA=2", "FUEL=3", "ORE=0"]);
+  let dot = to_dot(&formulas, "ORE");
+  assert!(dot.starts_with("digraph reactions {\n  rankdir=BT;\n  { rank=same; \"ORE\"; }\n  { rank=same; \"A\"; \"B\"; \"C\"; }\n"));
+  assert!(dot.contains("  \"FUEL\" [label=\"FUEL x1\\ndepth 3\"];\n"));
+  assert!(dot.contains("  \"ORE\" [label=\"ORE\\ndepth 0\"];\n"));
+  assert!(dot.contains("  \"BC\" -> \"FUEL\" [label=\"3\", weight=3];\n"));
+  assert_eq!(dot.matches(" -> ").count(), 12);
+  assert_eq!(dot, to_dot(&formulas, "ORE"));
+}
+
+#[test]
+fn test_zero_quantity() {
+  let issues = load("1 ORE => 0 A\n1 A => 1 FUEL", "FUEL", "ORE").expect_err("zero output must be rejected before solve");
+  assert_eq!(issues[0], RecipeIssue::Malformed { line: 1, message: "quantity of A must be positive".to_string() });
+  assert!(issues.iter().all(|i| !matches!(i, RecipeIssue::Malformed { line: 2, .. })));
+  let issues = load("1 ORE => 1 A\n0 A => 1 FUEL", "FUEL", "ORE").expect_err("zero input must be rejected before solve");
+  assert_eq!(issues[0].to_string(), "line 2: quantity of A must be positive");
+}
+
+#[test]
+fn test_solve_overflow() {
+  // Exact past u64: 2 A per FUEL, 3 ORE per A.
+  let small = load("3 ORE => 1 A\n2 A => 1 FUEL", "FUEL", "ORE").unwrap();
+  assert_eq!(min_source(&small, "FUEL", u64::MAX, "ORE"), Ok(6 * u64::MAX as u128));
+  // 100^26 ORE per FUEL does not fit in a u128: the 100^20 C5 already overflows.
+  let mut chain = String::from("100 ORE => 1 C0\n");
+  for i in 1..25 {
+    chain += &format!("100 C{} => 1 C{}\n", i - 1, i);
+  }
+  chain += "100 C24 => 1 FUEL\n";
+  let chain = load(&chain, "FUEL", "ORE").unwrap();
+  assert_eq!(min_source(&chain, "FUEL", 1, "ORE"), Err(SolveError::Overflow("C5".to_string())));
+  assert_eq!(min_source(&chain, "C17", 1, "ORE"), Ok(10u128.pow(36)));
+  assert_eq!(max_target(&chain, "FUEL", "ORE", u128::MAX), Ok(0));
+  assert_eq!(max_target(&chain, "C17", "ORE", 10u128.pow(38)), Ok(100));
+}
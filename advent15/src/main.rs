@@ -3,7 +3,7 @@ use std::fs;
 use std::collections::HashMap;
 use std::collections::VecDeque;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct State {
   ip: usize, // instruction pointer
   rb: i64, // relative base
@@ -172,182 +172,237 @@ fn follow_dir(pos: &Point, dir: i64) -> Point {
   return npos;
 }
 
-fn found_return_path(pos : &Point, pathdir : &HashMap<Point,i64>) -> VecDeque<i64> {
-  let mut path : VecDeque<i64> = VecDeque::new();  
-  let mut cur = *pos;
-  loop {
-    let dir = *pathdir.get(&cur).expect("should have a direction");
-    if dir == 0 { 
-      return path; 
-    };
-    let invdir = match dir {
-      1 => 2,
-      2 => 1,
-      3 => 4,
-      4 => 3,
-      _ => panic!("unexpected direction: {}", dir)
-    };
-    cur = follow_dir(&cur, invdir);
-    path.push_back(invdir);
+fn reverse_dir(dir: i64) -> i64 {
+  match dir {
+    1 => 2,
+    2 => 1,
+    3 => 4,
+    4 => 3,
+    _ => panic!("unexpected direction: {}", dir)
   }
 }
 
-fn found_path(pos : &Point, goal : &Point, pathdir : &HashMap<Point,i64>) -> VecDeque<i64> {
-  let mut going_path : VecDeque<i64> = VecDeque::new();
-  let mut cur = *pos;
-  if pathdir.contains_key(&(goal.0,goal.1+1)) {
-    cur = (goal.0,goal.1+1);
-    going_path.push_front(1);
-  } else if pathdir.contains_key(&(goal.0,goal.1-1)) {
-    cur = (goal.0,goal.1-1);
-    going_path.push_front(2);
-  } else if pathdir.contains_key(&(goal.0+1,goal.1)) {
-    cur = (goal.0+1,goal.1);
-    going_path.push_front(3);
-  } else if pathdir.contains_key(&(goal.0-1,goal.1)) {
-    cur = (goal.0-1,goal.1);
-    going_path.push_front(4);
-  }
-  loop {
-    let dir = *pathdir.get(&cur).expect("should have a direction");
-    if dir == 0 { break };
-    let invdir = match dir {
-      1 => 2,
-      2 => 1,
-      3 => 4,
-      4 => 3,
-      _ => panic!("unexpected direction: {}", dir)
-    };
-    cur = follow_dir(&cur, invdir);
-    going_path.push_front(dir);
-    //println!("fwd: {}", dir);
-  }
-  let mut path = found_return_path(pos, pathdir);
-  while going_path.len() > 0 {
-    path.push_back(going_path.pop_front().expect("checked above"));
-  }
-  return path;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+  Wall,  // did not move
+  Moved,
+  Found, // moved, onto the cell being searched for
 }
 
-fn droid(state : &mut State, explore_fully : bool) -> (Grid, usize, Point) {
-  let neighbor = [(0,1),(1,0),(0,-1),(-1,0)];
-  let mut grid = Grid::new();
-  let mut path : VecDeque<i64> = VecDeque::new();
-  let mut pathdir : HashMap<Point,i64> = HashMap::new();
-  let mut p : Point = (0,0);
-  let mut oxygen : Point = (0,0);
-  let mut open : VecDeque<Point> = VecDeque::new();
-  open.push_back((0,1));
-  open.push_back((1,0));
-  open.push_back((0,-1));
-  grid.insert((0,0), '.');
-  pathdir.insert((0,0), 0);
-  path.push_back(1);
-  while !state.finished {
+// A robot taking movement commands 1-4 (north, south, west, east) and
+// telling what happened. Cloning it takes a snapshot that can be moved
+// independently of the original.
+trait MoveRobot: Clone {
+  fn try_move(&mut self, dir: i64) -> Status;
+}
+
+impl MoveRobot for State {
+  fn try_move(&mut self, dir: i64) -> Status {
     let mut input : VecDeque<i64> = VecDeque::new();
-    let dir = path.pop_front().expect("should not be empty");
     input.push_back(dir);
-    let np = follow_dir(&p, dir);
-    //println!("p: {:?} np: {:?}", p, np);
-    //println!("input: {:?}", input);
-    let mut output = state.process(&mut input);
-    //println!("output: {:?}", output);
-    let status = output.pop_front().expect("should have a status output");
-    match status {
-      0 => { // hit a wall
-        grid.insert(np, '#');
-        //path = search_new_path();
-      },
-      1 => { // move ok
-        grid.insert(np, '.');
-        p = np;
-      },
-      2 => { // move ok, oxygen
-        grid.insert(np, 'o');
-        p = np;
-        oxygen = p;
-        if !explore_fully {
-          pathdir.insert(p, dir);
-          return (grid,found_return_path(&p, &pathdir).len(),oxygen);
-        }
-      },
-      _ => panic!("unexpected status")
-    };
-    if path.len() == 0 {
-      if status != 0 {
-        pathdir.insert(p, dir);
-        for n in &neighbor { 
-          let nbp = (p.0+n.0,p.1+n.1);
-          if !grid.contains_key(&nbp) { 
-            open.push_back(nbp); 
-          }
-        }
+    let mut output = self.process(&mut input);
+    match output.pop_front().expect("should have a status output") {
+      0 => Status::Wall,
+      1 => Status::Moved,
+      2 => Status::Found,
+      s => panic!("unexpected status: {}", s)
+    }
+  }
+}
+
+// Everything a robot has seen: '.' open, '#' wall, 'o' where it got
+// `Status::Found`. Positions are relative to where it started.
+struct AreaMap {
+  grid: Grid,
+  start: Point,
+}
+
+// Maps every reachable cell depth first. Each open cell keeps a snapshot
+// of the robot standing on it, and every neighbor is tried from a copy of
+// that snapshot, so the robot never has to walk back. `robot` itself is
+// left untouched.
+fn explore<R: MoveRobot>(robot: &R) -> AreaMap {
+  let start = (0,0);
+  let mut grid = Grid::new();
+  grid.insert(start, '.');
+  let mut stack : Vec<(Point, R)> = vec![(start, robot.clone())];
+  while let Some((pos, snapshot)) = stack.pop() {
+    for dir in 1..=4 {
+      let npos = follow_dir(&pos, dir);
+      if grid.contains_key(&npos) {
+        continue;
       }
-      if open.len() == 0 && explore_fully {
-        return (grid,0,oxygen);
+      let mut moved = snapshot.clone();
+      match moved.try_move(dir) {
+        Status::Wall => { grid.insert(npos, '#'); }
+        status => {
+          grid.insert(npos, if status == Status::Found { 'o' } else { '.' });
+          stack.push((npos, moved));
+        }
       }
-      let goal = open.pop_front().expect("should not be out of option before oxygen tank found");
-      //println!("New goal: {:?}", goal);
-      path = found_path(&p, &goal, &pathdir);
-      //println!("Path: {:?}", path);
-      /*println!("*********************");
-      print_grid_minmax(&grid, -20, 20, -20, 20);*/
     }
-    
-    /*println!("*********************");
-    print_grid_minmax(&grid, -10, 10, -10, 10);*/
-    /*println!("count {} move {} color {}", grid.len(), movement, color);
-    */
-    //print_grid(&grid);
   }
-  panic!("should not exit");
+  AreaMap { grid, start }
 }
 
-fn expand_oxygen(from : &Point, grid : &Grid) -> usize {
-  let neighbor = [(0,1),(1,0),(0,-1),(-1,0)];
-  let mut open : VecDeque<Point> = VecDeque::new();
-  let mut close : HashMap<Point, usize> = HashMap::new();
-  let mut max = 0;
-  close.insert(*from, 0);
-  open.push_back(*from);
-  while open.len() > 0 {
-    let p = open.pop_front().expect("checked above");
-    let cost = *close.get(&p).unwrap();
-    for n in &neighbor { 
-      let nbp = (p.0+n.0,p.1+n.1);
-      if !close.contains_key(&nbp) && *grid.get(&nbp).expect("map is bounded") == '.' { 
-        close.insert(nbp, cost+1);
-        open.push_back(nbp);
-        max = cost+1;
+impl AreaMap {
+  fn is_open(&self, pos: &Point) -> bool {
+    matches!(self.grid.get(pos), Some('.') | Some('o'))
+  }
+
+  fn found(&self) -> Vec<Point> {
+    let mut found : Vec<Point> = self.grid.iter().filter(|(_, c)| **c == 'o').map(|(p, _)| *p).collect();
+    found.sort();
+    found
+  }
+
+  // Breadth first search from `from`: the distance to each open cell and
+  // the direction taken to reach it.
+  fn search(&self, from: &Point) -> HashMap<Point, (usize, i64)> {
+    let mut close : HashMap<Point, (usize, i64)> = HashMap::new();
+    let mut open : VecDeque<Point> = VecDeque::new();
+    if !self.is_open(from) {
+      return close;
+    }
+    close.insert(*from, (0, 0));
+    open.push_back(*from);
+    while let Some(p) = open.pop_front() {
+      let cost = close[&p].0;
+      for dir in 1..=4 {
+        let nbp = follow_dir(&p, dir);
+        if self.is_open(&nbp) && !close.contains_key(&nbp) {
+          close.insert(nbp, (cost + 1, dir));
+          open.push_back(nbp);
+        }
       }
     }
+    close
+  }
+
+  // Movement commands of a shortest way from `from` to `to`.
+  fn shortest_path(&self, from: &Point, to: &Point) -> Option<Vec<i64>> {
+    let close = self.search(from);
+    close.get(to)?;
+    let mut path = Vec::new();
+    let mut cur = *to;
+    while cur != *from {
+      let dir = close[&cur].1;
+      path.push(dir);
+      cur = follow_dir(&cur, reverse_dir(dir));
+    }
+    path.reverse();
+    Some(path)
+  }
+
+  // Minutes for oxygen released at `from` to fill every open cell it can
+  // reach, spreading one cell per minute.
+  fn fill_time(&self, from: &Point) -> usize {
+    self.search(from).values().map(|(d, _)| *d).max().unwrap_or(0)
   }
-  return max;
 }
 
-fn q1(filename: impl AsRef<std::path::Path>) -> usize {
-  let mut state = State::new_from_file(filename);
-  let (grid, length, _) = droid(&mut state, false);
-  print_grid(&grid);
-  return length;
+fn oxygen(map: &AreaMap) -> Point {
+  *map.found().first().expect("oxygen system not found")
 }
 
-fn q2(filename: impl AsRef<std::path::Path>) -> usize {
-  let mut state = State::new_from_file(filename);
-  let (grid, _, oxygen) = droid(&mut state, true);
-  print_grid(&grid);
-  return expand_oxygen(&oxygen, &grid);
+fn q1(map: &AreaMap) -> usize {
+  map.shortest_path(&map.start, &oxygen(map)).expect("oxygen system unreachable").len()
 }
 
-/*
-fn q2(filename: impl AsRef<std::path::Path>) -> i64 {
-  let mut state = State::new_from_file(filename);
-  state.set_mem(0,2);
-  return game(&mut state).1;
+fn q2(map: &AreaMap) -> usize {
+  map.fill_time(&oxygen(map))
 }
-*/
+
 fn main() {
-  println!("Question1: {}", q1("data.txt"));
-  println!("Question2: {}", q2("data.txt"));
-  //println!("Question2: {}", q2("data.txt"));
+  let map = explore(&State::new_from_file("data.txt"));
+  print_grid(&map.grid);
+  println!("Question1: {}", q1(&map));
+  println!("Question2: {}", q2(&map));
+}
+
+// A robot in a maze drawn with '.' open cells, 'S' its start and 'O' the
+// goal; everything else is wall.
+#[cfg(test)]
+#[derive(Clone)]
+struct MazeRobot {
+  open: std::collections::HashSet<Point>,
+  goal: Point,
+  pos: Point,
+  // Every successful move made by this robot or any of its snapshots.
+  moves: std::rc::Rc<std::cell::RefCell<Vec<Point>>>,
+}
+
+#[cfg(test)]
+impl MazeRobot {
+  fn new(maze: &str) -> MazeRobot {
+    let mut robot = MazeRobot { open: Default::default(), goal: (-1,-1), pos: (0,0), moves: Default::default() };
+    for (y, line) in maze.lines().enumerate() {
+      for (x, c) in line.chars().enumerate() {
+        let p = (x as i32, y as i32);
+        match c {
+          '.' => {}
+          'S' => robot.pos = p,
+          'O' => robot.goal = p,
+          _ => continue,
+        }
+        robot.open.insert(p);
+      }
+    }
+    robot
+  }
+}
+
+#[cfg(test)]
+impl MoveRobot for MazeRobot {
+  fn try_move(&mut self, dir: i64) -> Status {
+    let np = follow_dir(&self.pos, dir);
+    if !self.open.contains(&np) {
+      return Status::Wall;
+    }
+    self.pos = np;
+    self.moves.borrow_mut().push(np);
+    if np == self.goal { Status::Found } else { Status::Moved }
+  }
+}
+
+#[test]
+fn test_explore() {
+  let map = explore(&MazeRobot::new("#S.#"));
+  assert_eq!(map.grid.values().filter(|c| **c == '.').count(), 2);
+  assert_eq!(map.grid.values().filter(|c| **c == '#').count(), 6);
+  assert!(map.found().is_empty());
+
+  let robot = MazeRobot::new("\
+#####\n\
+#..##\n\
+#.#.#\n\
+#.O.#\n\
+#S.##\n\
+#####\n");
+  let start = robot.pos;
+  let map = explore(&robot);
+  assert_eq!(robot.pos, start);
+  assert_eq!(map.grid.values().filter(|c| **c != '#').count(), 9);
+  // Snapshots never walk back: each open cell but the start is entered
+  // exactly once, by a single move.
+  let mut moves = robot.moves.borrow().clone();
+  assert_eq!(moves.len(), 8);
+  moves.sort();
+  moves.dedup();
+  assert_eq!(moves.len(), 8);
+  assert!(!moves.contains(&start));
+  let o = (2 - start.0, 3 - start.1);
+  assert_eq!(map.found(), vec![o]);
+  assert_eq!(map.shortest_path(&map.start, &o).map(|p| p.len()), Some(2));
+  assert_eq!(map.shortest_path(&map.start, &(1, -3)), Some(vec![1, 1, 1, 4]));
+  assert_eq!(map.shortest_path(&map.start, &(100, 100)), None);
+  assert_eq!(map.fill_time(&o), 4);
+  assert_eq!(map.fill_time(&map.start), 4);
+}
+
+#[test]
+fn test_answers() {
+  let map = explore(&State::new_from_file("data.txt"));
+  assert_eq!(q1(&map), 228);
+  assert_eq!(q2(&map), 348);
 }
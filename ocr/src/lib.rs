@@ -0,0 +1,101 @@
+// Reader for the block letters some puzzles draw (days 8 and 11): capitals
+// 6 pixels high, 4 wide for most of them, separated by empty columns.
+
+const HEIGHT : usize = 6;
+
+// Glyphs are stored trimmed to their lit columns, as they are compared
+// after the same trimming.
+const FONT : [(char, [&str; HEIGHT]); 18] = [
+  ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+  ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+  ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+  ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+  ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+  ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+  ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+  ('I', ["###", ".#.", ".#.", ".#.", ".#.", "###"]),
+  ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+  ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+  ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+  ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+  ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+  ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+  ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+  ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+  ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+  ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownGlyph {
+  // position of the glyph in the text, and of its first column in the image
+  pub index: usize,
+  pub column: usize,
+  // one line per pixel row, `#` for lit pixels
+  pub bitmap: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ocr {
+  // the decoded text, with `?` in place of each unknown glyph
+  pub text: String,
+  pub unknown: Vec<UnknownGlyph>,
+}
+
+fn glyph_rows(pixels: &[Vec<bool>], from: usize, to: usize) -> Vec<String> {
+  pixels.iter().map(|row| (from..to).map(|x| if row.get(x).cloned().unwrap_or(false) { '#' } else { '.' }).collect()).collect()
+}
+
+// Decode an image given as rows of pixels, `true` being lit. Blank rows
+// above the letters are skipped; letters are split on empty columns.
+pub fn recognize(pixels: &[Vec<bool>]) -> Ocr {
+  let top = pixels.iter().position(|row| row.iter().any(|p| *p)).unwrap_or(0);
+  let rows = &pixels[top..pixels.len().min(top + HEIGHT)];
+  let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+  let lit = |x: usize| rows.iter().any(|r| r.get(x).cloned().unwrap_or(false));
+  let mut ocr = Ocr { text: String::new(), unknown: Vec::new() };
+  let mut x = 0;
+  while x < width {
+    if !lit(x) {
+      x += 1;
+      continue;
+    }
+    let start = x;
+    while x < width && lit(x) {
+      x += 1;
+    }
+    let mut bitmap = glyph_rows(rows, start, x);
+    bitmap.resize(HEIGHT, ".".repeat(x - start));
+    match FONT.iter().find(|(_, glyph)| glyph.iter().zip(bitmap.iter()).all(|(a, b)| a == b)) {
+      Some((c, _)) => ocr.text.push(*c),
+      None => {
+        ocr.unknown.push(UnknownGlyph { index: ocr.text.chars().count(), column: start, bitmap });
+        ocr.text.push('?');
+      }
+    }
+  }
+  ocr
+}
+
+#[test]
+fn test_recognize() {
+  let image = [
+    "..##..#..#.####.....",
+    "...#..#..#....#.....",
+    "...#..####...#......",
+    "...#..#..#..#.......",
+    "#..#..#..#.#........",
+    ".##...#..#.####.....",
+  ];
+  let pixels : Vec<Vec<bool>> = image.iter().map(|r| r.chars().map(|c| c == '#').collect()).collect();
+  assert_eq!(recognize(&pixels), Ocr { text: "JHZ".to_string(), unknown: Vec::new() });
+}
+
+#[test]
+fn test_unknown_glyph() {
+  let image = ["", "#.#..###", ".#....#.", "#.#...#.", "......#.", "......#.", ".....###"];
+  let pixels : Vec<Vec<bool>> = image.iter().map(|r| r.chars().map(|c| c == '#').collect()).collect();
+  let ocr = recognize(&pixels);
+  assert_eq!(ocr.text, "?I");
+  assert_eq!(ocr.unknown, vec![UnknownGlyph { index: 0, column: 0, bitmap: vec!["#.#".to_string(), ".#.".to_string(), "#.#".to_string(), "...".to_string(), "...".to_string(), "...".to_string()] }]);
+}